@@ -0,0 +1,510 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use pcap::{Activated, Active, Capture};
+use protobuf::reflect::{ReflectFieldRef, ReflectValueRef};
+use protobuf::{Message, UnknownValueRef};
+use serde_json::json;
+
+use zwift_capture::builder::ZwiftCaptureBuilder;
+use zwift_capture::error::ZwiftCaptureError;
+use zwift_capture::events::GameEvent;
+use zwift_capture::groups::GroupTracker;
+use zwift_capture::metrics::MetricsExporter;
+use zwift_capture::quality::QualityTracker;
+use zwift_capture::race::EventTracker;
+use zwift_capture::roster::RiderDirectory;
+#[cfg(feature = "server")]
+use zwift_capture::server::LiveServer;
+use zwift_capture::{DecodedMessage, Direction, Event, ZwiftCapture};
+
+const USAGE: &str = "usage:
+    zwift-capture live [--device NAME] [--filter BPF] [--format players|events|groups|race]
+        [--serve ADDR] [--metrics ADDR] [--roster FILE]
+    zwift-capture replay <pcap> [--filter BPF] [--format players|events|groups|race]
+        [--serve ADDR] [--metrics ADDR] [--roster FILE]
+    zwift-capture dump [<pcap> | --device NAME] [--filter BPF]
+    zwift-capture stats [<pcap> | --device NAME] [--filter BPF] [--interval SECS]
+        [--metrics ADDR]
+
+options:
+    --device NAME    capture device, default device if omitted
+    --promisc        put the device into promiscuous mode
+    --snaplen BYTES  bytes captured per packet
+    --immediate      deliver packets without buffering
+    --filter BPF     extra bpf expression, combined with the zwift ports filter
+    --format FORMAT  json lines of players (default), game events, groups on the road
+                     or race standings
+    --interval SECS  live stats period, default 10
+    --serve ADDR     also publish riders over http and websocket, needs server feature
+    --metrics ADDR   expose openmetrics on http://ADDR/metrics
+    --roster FILE    json file of rider names kept between sessions";
+
+// learned rider names are written back this often
+const ROSTER_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+// live reads wake up this often to report stats while idle, millis
+const READ_TIMEOUT: i32 = 500;
+
+enum Command {
+    Live,
+    Replay,
+    Dump,
+    Stats,
+}
+
+enum Source {
+    Device(Option<String>),
+    File(PathBuf),
+}
+
+enum Format {
+    Players,
+    Events,
+    Groups,
+    Race,
+}
+
+struct Options {
+    command: Command,
+    source: Source,
+    promisc: bool,
+    snaplen: Option<i32>,
+    immediate: bool,
+    filter: Option<String>,
+    format: Format,
+    interval: Duration,
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    serve: Option<String>,
+    metrics: Option<String>,
+    roster: Option<PathBuf>,
+}
+
+type BoxResult<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> BoxResult<Options> {
+    let command = match args.next().as_deref() {
+        Some("live") => Command::Live,
+        Some("replay") => Command::Replay,
+        Some("dump") => Command::Dump,
+        Some("stats") => Command::Stats,
+        Some(other) => return Err(format!("unknown command {}", other).into()),
+        None => return Err("missing command".into()),
+    };
+    let mut device = None;
+    let mut promisc = false;
+    let mut snaplen = None;
+    let mut immediate = false;
+    let mut file = None;
+    let mut filter = None;
+    let mut format = Format::Players;
+    let mut interval = Duration::from_secs(10);
+    let mut serve = None;
+    let mut metrics = None;
+    let mut roster = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--device" => device = Some(value()?),
+            "--promisc" => promisc = true,
+            "--snaplen" => snaplen = Some(value()?.parse()?),
+            "--immediate" => immediate = true,
+            "--filter" => filter = Some(value()?),
+            "--format" => {
+                format = match value()?.as_str() {
+                    "players" => Format::Players,
+                    "events" => Format::Events,
+                    "groups" => Format::Groups,
+                    "race" => Format::Race,
+                    other => return Err(format!("unknown format {}", other).into()),
+                }
+            }
+            "--interval" => interval = Duration::from_secs(value()?.parse()?),
+            "--metrics" => metrics = Some(value()?),
+            "--roster" => roster = Some(PathBuf::from(value()?)),
+            "--serve" if cfg!(feature = "server") => serve = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ if file.is_none() => file = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {}", arg).into()),
+        }
+    }
+    let source = match (&command, file, device) {
+        (Command::Live, Some(_), _) => return Err("live takes no pcap file".into()),
+        (Command::Replay, None, _) => return Err("replay needs a pcap file".into()),
+        (Command::Replay, Some(_), Some(_)) => return Err("replay takes no device".into()),
+        (_, Some(_), Some(_)) => return Err("use either a pcap file or a device".into()),
+        (_, Some(file), None) => Source::File(file),
+        (_, None, device) => Source::Device(device),
+    };
+    match (&command, &serve) {
+        (Command::Live, _) | (Command::Replay, _) | (_, None) => {}
+        _ => return Err("only live and replay can serve".into()),
+    }
+    Ok(Options {
+        command,
+        source,
+        promisc,
+        snaplen,
+        immediate,
+        filter,
+        format,
+        interval,
+        serve,
+        metrics,
+        roster,
+    })
+}
+
+fn open_device(
+    options: &Options,
+    name: &Option<String>,
+) -> BoxResult<ZwiftCapture<Capture<Active>>> {
+    let mut builder = ZwiftCaptureBuilder::new()
+        .promisc(options.promisc)
+        .immediate_mode(options.immediate)
+        .timeout(READ_TIMEOUT);
+    if let Some(name) = name {
+        builder = builder.device(name);
+    }
+    if let Some(snaplen) = options.snaplen {
+        builder = builder.snaplen(snaplen);
+    }
+    Ok(builder.open()?)
+}
+
+fn run<T: Activated>(options: &Options, mut capture: ZwiftCapture<Capture<T>>) -> BoxResult<()> {
+    if let Some(filter) = &options.filter {
+        capture.add_filter(filter)?;
+    }
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut stats = Stats::new();
+    let mut last_report = Instant::now();
+    #[cfg(feature = "server")]
+    let server = match &options.serve {
+        Some(address) => {
+            let server = LiveServer::bind(address.as_str())?;
+            eprintln!("zwift-capture: serving on http://{}", server.local_addr());
+            Some(server)
+        }
+        None => None,
+    };
+    let metrics = match &options.metrics {
+        Some(address) => {
+            let exporter = MetricsExporter::bind(address.as_str())?;
+            eprintln!(
+                "zwift-capture: metrics on http://{}/metrics",
+                exporter.local_addr()
+            );
+            Some(exporter.metrics())
+        }
+        None => None,
+    };
+    // one directory for output, the server and saving
+    let roster = Arc::new(Mutex::new(RiderDirectory::new()));
+    #[cfg(feature = "server")]
+    let roster = server.as_ref().map_or(roster, |server| server.roster());
+    if let Some(path) = &options.roster {
+        *roster.lock().unwrap() = RiderDirectory::load(path)?;
+    }
+    let mut groups = GroupTracker::new();
+    let mut race = EventTracker::new();
+    let mut last_save = Instant::now();
+    let mut last_stat = Instant::now();
+    while let Some(event) = capture.poll_event() {
+        let timed_out = matches!(
+            event,
+            Err(ZwiftCaptureError::Pcap(pcap::Error::TimeoutExpired))
+        );
+        if let (Command::Stats, Source::Device(_)) = (&options.command, &options.source) {
+            if last_report.elapsed() >= options.interval {
+                last_report = Instant::now();
+                if reader_gone(stats.write(&mut out, &mut capture))? {
+                    roster.lock().unwrap().save()?;
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(metrics) = &metrics {
+            let mut metrics = metrics.lock().unwrap();
+            if !timed_out {
+                metrics.observe(&event);
+            }
+            if last_stat.elapsed() >= Duration::from_secs(1) {
+                last_stat = Instant::now();
+                if let Ok(stat) = capture.stats() {
+                    metrics.observe_stat(stat);
+                }
+            }
+        }
+        let event = match event {
+            Ok(event) => event,
+            // idle live capture
+            Err(_) if timed_out => continue,
+            // capture itself is broken, no point in reading further
+            Err(ZwiftCaptureError::Pcap(error)) => {
+                roster.lock().unwrap().save()?;
+                return Err(error.into());
+            }
+            Err(error) => {
+                stats.errors += 1;
+                if let Command::Dump = options.command {
+                    writeln!(out, "# error: {}", error)?;
+                } else {
+                    eprintln!("zwift-capture: {}", error);
+                }
+                continue;
+            }
+        };
+        roster.lock().unwrap().ingest(&event);
+        if last_save.elapsed() >= ROSTER_SAVE_INTERVAL {
+            last_save = Instant::now();
+            roster.lock().unwrap().save()?;
+        }
+        let written = match options.command {
+            Command::Live | Command::Replay => write_event(
+                &mut out,
+                &options.format,
+                &roster.lock().unwrap(),
+                &mut groups,
+                &mut race,
+                &event,
+            ),
+            Command::Dump => dump_event(&mut out, &event),
+            Command::Stats => {
+                stats.add(&event);
+                Ok(())
+            }
+        };
+        if reader_gone(written)? {
+            roster.lock().unwrap().save()?;
+            return Ok(());
+        }
+        #[cfg(feature = "server")]
+        if let Some(server) = &server {
+            server.publish(&event);
+        }
+    }
+    roster.lock().unwrap().save()?;
+    if let Command::Stats = options.command {
+        stats.write(&mut out, &mut capture)?;
+    }
+    #[cfg(feature = "server")]
+    if let Some(server) = server {
+        // replayed files end quickly, keep serving what was tracked
+        server.wait();
+    }
+    Ok(())
+}
+
+// reader went away, e.g. piped into head
+fn reader_gone(written: io::Result<()>) -> io::Result<bool> {
+    match written {
+        Ok(()) => Ok(false),
+        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => Ok(true),
+        Err(error) => Err(error),
+    }
+}
+
+fn write_event(
+    out: &mut impl Write,
+    format: &Format,
+    roster: &RiderDirectory,
+    groups: &mut GroupTracker,
+    race: &mut EventTracker,
+    event: &Event,
+) -> io::Result<()> {
+    match format {
+        Format::Players => {
+            for player in &event.players {
+                writeln!(out, "{}", serde_json::to_string(&roster.enrich(player))?)?;
+            }
+        }
+        Format::Events => {
+            for game_event in &event.game_events {
+                writeln!(out, "{}", serde_json::to_string(game_event)?)?;
+            }
+        }
+        Format::Groups => {
+            let updated = groups.updated();
+            for group_event in groups.ingest(event) {
+                writeln!(out, "{}", serde_json::to_string(&group_event)?)?;
+            }
+            if groups.updated() != updated {
+                let line = json!({
+                    "world_time": groups.updated(),
+                    "groups": groups.groups(),
+                });
+                writeln!(out, "{}", line)?;
+            }
+        }
+        Format::Race => {
+            for change in race.ingest(event) {
+                writeln!(out, "{}", serde_json::to_string(&change)?)?;
+            }
+            if let DecodedMessage::FromServer(message) = &event.message {
+                if message.has_event_positions() {
+                    writeln!(out, "{}", serde_json::to_string(&race.standings())?)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn dump_event(out: &mut impl Write, event: &Event) -> io::Result<()> {
+    let mut unknown = vec![];
+    match &event.message {
+        DecodedMessage::FromServer(message) => {
+            writeln!(out, "# from server")?;
+            writeln!(out, "{:#?}", message)?;
+            unknown_fields(message, "", &mut unknown);
+        }
+        DecodedMessage::ToServer(header, message) => {
+            writeln!(
+                out,
+                "# to server, connection {} sequence {}",
+                header.connection_id, header.sequence
+            )?;
+            writeln!(out, "{:#?}", message)?;
+            unknown_fields(message, "", &mut unknown);
+        }
+    }
+    for line in unknown {
+        writeln!(out, "unknown {}", line)?;
+    }
+    for game_event in &event.game_events {
+        writeln!(out, "game event {:?}", game_event)?;
+    }
+    Ok(())
+}
+
+// fields missing from the proto file, text format skips them
+fn unknown_fields(message: &dyn Message, path: &str, out: &mut Vec<String>) {
+    for (number, values) in message.get_unknown_fields() {
+        for value in values {
+            let value = match value {
+                UnknownValueRef::Fixed32(value) => format!("fixed32 {}", value),
+                UnknownValueRef::Fixed64(value) => format!("fixed64 {}", value),
+                UnknownValueRef::Varint(value) => format!("varint {}", value),
+                UnknownValueRef::LengthDelimited(bytes) => {
+                    let hex: Vec<_> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                    format!("bytes {}", hex.concat())
+                }
+            };
+            out.push(format!("{}{}: {}", path, number, value));
+        }
+    }
+    for field in message.descriptor().fields() {
+        match field.get_reflect(message) {
+            ReflectFieldRef::Optional(Some(ReflectValueRef::Message(nested))) => {
+                unknown_fields(nested, &format!("{}{}.", path, field.name()), out);
+            }
+            ReflectFieldRef::Repeated(repeated) => {
+                for (index, value) in repeated.reflect_iter().enumerate() {
+                    if let ReflectValueRef::Message(nested) = value.as_ref() {
+                        let path = format!("{}{}[{}].", path, field.name(), index);
+                        unknown_fields(nested, &path, out);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Stats {
+    started: Instant,
+    from_server: u64,
+    to_server: u64,
+    players: u64,
+    errors: u64,
+    game_events: BTreeMap<&'static str, u64>,
+    quality: QualityTracker,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Stats {
+            started: Instant::now(),
+            from_server: 0,
+            to_server: 0,
+            players: 0,
+            errors: 0,
+            game_events: BTreeMap::new(),
+            quality: QualityTracker::new(),
+        }
+    }
+
+    fn add(&mut self, event: &Event) {
+        match event.direction() {
+            Direction::FromServer => self.from_server += 1,
+            Direction::ToServer => self.to_server += 1,
+        }
+        self.players += event.players.len() as u64;
+        self.quality.ingest(event);
+        for game_event in &event.game_events {
+            let kind = match game_event {
+                GameEvent::Chat { .. } => "chat",
+                GameEvent::RideOn { .. } => "ride_on",
+                GameEvent::RiderEnteredWorld { .. } => "rider_entered_world",
+                GameEvent::TimeSync { .. } => "time_sync",
+                GameEvent::Unknown { .. } => "unknown",
+            };
+            *self.game_events.entry(kind).or_insert(0) += 1;
+        }
+    }
+
+    fn write<T: Activated>(
+        &self,
+        out: &mut impl Write,
+        capture: &mut ZwiftCapture<Capture<T>>,
+    ) -> io::Result<()> {
+        // offline captures have no pcap stats
+        let pcap = capture.stats().ok().map(|stat| {
+            json!({
+                "received": stat.received,
+                "dropped": stat.dropped,
+                "if_dropped": stat.if_dropped,
+            })
+        });
+        let line = json!({
+            "elapsed": self.started.elapsed().as_secs_f64(),
+            "from_server": self.from_server,
+            "to_server": self.to_server,
+            "players": self.players,
+            "errors": self.errors,
+            "game_events": self.game_events,
+            "pcap": pcap,
+            "quality": self.quality.summary(),
+            "session": capture.session(),
+        });
+        writeln!(out, "{}", line)
+    }
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("zwift-capture: {}\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+    let result = match &options.source {
+        Source::Device(name) => {
+            open_device(&options, name).and_then(|capture| run(&options, capture))
+        }
+        Source::File(path) => ZwiftCapture::try_from_file(path)
+            .map_err(|error| error.into())
+            .and_then(|capture| run(&options, capture)),
+    };
+    if let Err(error) = result {
+        eprintln!("zwift-capture: {}", error);
+        process::exit(1);
+    }
+}
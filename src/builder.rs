@@ -0,0 +1,174 @@
+use std::path::Path;
+
+use pcap::{Active, Capture, Device, Offline};
+
+use crate::direction::ServerPorts;
+use crate::error::{Result, ZwiftCaptureError};
+use crate::ZwiftCapture;
+
+// capture options, libpcap defaults where not set
+#[derive(Debug, Clone, Default)]
+pub struct ZwiftCaptureBuilder {
+    device: Option<String>,
+    promisc: Option<bool>,
+    snaplen: Option<i32>,
+    buffer_size: Option<i32>,
+    timeout: Option<i32>, // millis
+    immediate_mode: Option<bool>,
+    filters: Vec<String>,
+    ports: ServerPorts,
+}
+
+impl ZwiftCaptureBuilder {
+    pub fn new() -> Self {
+        ZwiftCaptureBuilder::default()
+    }
+
+    // default device if not set
+    pub fn device(mut self, name: &str) -> Self {
+        self.device = Some(name.to_string());
+        self
+    }
+
+    pub fn promisc(mut self, promisc: bool) -> Self {
+        self.promisc = Some(promisc);
+        self
+    }
+
+    pub fn snaplen(mut self, snaplen: i32) -> Self {
+        self.snaplen = Some(snaplen);
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: i32) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    pub fn timeout(mut self, millis: i32) -> Self {
+        self.timeout = Some(millis);
+        self
+    }
+
+    // timeout unless one was set already
+    pub fn default_timeout(mut self, millis: i32) -> Self {
+        self.timeout = self.timeout.or(Some(millis));
+        self
+    }
+
+    // deliver packets as they arrive instead of buffering
+    pub fn immediate_mode(mut self, immediate_mode: bool) -> Self {
+        self.immediate_mode = Some(immediate_mode);
+        self
+    }
+
+    // extra bpf clause, all clauses and the ports must match
+    pub fn filter(mut self, program: &str) -> Self {
+        self.filters.push(program.to_string());
+        self
+    }
+
+    pub fn host(self, host: &str) -> Self {
+        self.filter(&format!("host {}", host))
+    }
+
+    pub fn ports(mut self, ports: ServerPorts) -> Self {
+        self.ports = ports;
+        self
+    }
+
+    fn lookup_device(&self) -> Result<Device> {
+        let name = match &self.device {
+            Some(name) => name,
+            None => return Ok(Device::lookup()?),
+        };
+        let devices = Device::list()?;
+        let available: Vec<String> = devices.iter().map(|device| device.name.clone()).collect();
+        devices
+            .into_iter()
+            .find(|device| &device.name == name)
+            .ok_or_else(|| ZwiftCaptureError::DeviceNotFound {
+                name: name.clone(),
+                available,
+            })
+    }
+
+    pub fn open(self) -> Result<ZwiftCapture<Capture<Active>>> {
+        let device = self.lookup_device()?;
+        let interface = device.name.clone();
+        let mut capture = Capture::from_device(device)?;
+        if let Some(promisc) = self.promisc {
+            capture = capture.promisc(promisc);
+        }
+        if let Some(snaplen) = self.snaplen {
+            capture = capture.snaplen(snaplen);
+        }
+        if let Some(buffer_size) = self.buffer_size {
+            capture = capture.buffer_size(buffer_size);
+        }
+        if let Some(timeout) = self.timeout {
+            capture = capture.timeout(timeout);
+        }
+        if let Some(immediate_mode) = self.immediate_mode {
+            capture = capture.immediate_mode(immediate_mode);
+        }
+        let mut capture = self.finish(capture.open()?)?;
+        capture.interface = Some(interface);
+        Ok(capture)
+    }
+
+    // live only options are ignored
+    pub fn open_file(self, path: &Path) -> Result<ZwiftCapture<Capture<Offline>>> {
+        self.finish(Capture::from_file(path)?)
+    }
+
+    fn finish<T: pcap::Activated>(self, capture: Capture<T>) -> Result<ZwiftCapture<Capture<T>>> {
+        let mut capture = ZwiftCapture::with_capture(capture);
+        capture.filters = self.filters;
+        capture.set_server_ports(self.ports)?;
+        Ok(capture)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use hex_literal::hex;
+
+    use crate::builder::ZwiftCaptureBuilder;
+    use crate::fixtures::TempCapture;
+    use crate::CAPTURE_FILTER;
+
+    #[test]
+    fn combine_filters() {
+        let builder = ZwiftCaptureBuilder::new();
+        assert_eq!(builder.ports.filter_with(&builder.filters), CAPTURE_FILTER);
+        let builder = builder.host("10.0.0.1").filter("not port 53");
+        assert_eq!(
+            builder.ports.filter_with(&builder.filters),
+            "(udp port 3022 or tcp port 3023) and (host 10.0.0.1) and (not port 53)"
+        );
+        assert_eq!(builder.clone().default_timeout(250).timeout, Some(250));
+        let builder = builder.timeout(1000).default_timeout(250);
+        assert_eq!(builder.timeout, Some(1000));
+    }
+
+    #[test]
+    fn open_file_with_host_filter() {
+        let payload = hex!("08011086d30618d5a3fbcce805");
+        let file = TempCapture::new(&payload, &[1_600_000_000]);
+
+        let mut capture = ZwiftCaptureBuilder::new()
+            .host("10.0.0.1")
+            .open_file(file.path())
+            .unwrap();
+        assert_eq!(capture.events().count(), 1);
+        // later filters keep the host restriction
+        let mut capture = ZwiftCaptureBuilder::new()
+            .host("10.9.9.9")
+            .open_file(file.path())
+            .unwrap();
+        capture.add_filter("udp").unwrap();
+        assert_eq!(capture.events().count(), 0);
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+
+use crate::events::GameEvent;
+use crate::fit::ZWIFT_EPOCH_MS;
+use crate::{DecodedMessage, Event};
+
+// seconds of world time kept for the fit
+pub const DEFAULT_WINDOW: usize = 600;
+
+// maps world time to utc from capture timestamps of messages stamped with world time
+//   offset = capture time - world time, the smallest per second is kept as it has
+//   the least network delay, drift is the slope of a least squares line through them
+pub struct WorldClock {
+    samples: VecDeque<(i64, f64)>, // world time, offset in millis
+    window: usize,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        WorldClock::new(DEFAULT_WINDOW)
+    }
+}
+
+impl WorldClock {
+    pub fn new(window: usize) -> Self {
+        WorldClock {
+            samples: VecDeque::new(),
+            window: window.max(1),
+        }
+    }
+
+    pub fn ingest(&mut self, event: &Event) {
+        match &event.message {
+            DecodedMessage::FromServer(message) => {
+                self.observe(message.get_world_time(), event.capture_time);
+            }
+            DecodedMessage::ToServer(_, message) => {
+                self.observe(message.get_world_time(), event.capture_time);
+            }
+        }
+        for game_event in &event.game_events {
+            if let GameEvent::TimeSync { world_time, .. } = game_event {
+                self.observe(*world_time, event.capture_time);
+            }
+        }
+    }
+
+    // capture time in micros since unix epoch
+    pub fn observe(&mut self, world_time: i64, capture_time: i64) {
+        if world_time <= 0 || capture_time <= 0 {
+            return;
+        }
+        let offset = capture_time as f64 / 1000. - world_time as f64;
+        let second = world_time.div_euclid(1000);
+        match self.samples.back_mut() {
+            Some((last, last_offset)) if last.div_euclid(1000) == second => {
+                if offset < *last_offset {
+                    *last = world_time;
+                    *last_offset = offset;
+                }
+            }
+            Some((last, _)) if last.div_euclid(1000) > second => {}
+            _ => self.samples.push_back((world_time, offset)),
+        }
+        while self.samples.len() > self.window {
+            self.samples.pop_front();
+        }
+    }
+
+    // intercept at the first sample and slope
+    fn fit(&self) -> Option<(i64, f64, f64)> {
+        let (origin, _) = *self.samples.front()?;
+        let count = self.samples.len() as f64;
+        let (mut sum_x, mut sum_y, mut sum_xx, mut sum_xy) = (0., 0., 0., 0.);
+        for (world_time, offset) in &self.samples {
+            let x = (world_time - origin) as f64;
+            sum_x += x;
+            sum_y += offset;
+            sum_xx += x * x;
+            sum_xy += x * offset;
+        }
+        let variance = count * sum_xx - sum_x * sum_x;
+        let slope = if variance > 0. {
+            (count * sum_xy - sum_x * sum_y) / variance
+        } else {
+            0.
+        };
+        let intercept = (sum_y - slope * sum_x) / count;
+        Some((origin, intercept, slope))
+    }
+
+    // millis since unix epoch, falls back to the fixed zwift epoch without samples
+    pub fn to_utc(&self, world_time: i64) -> i64 {
+        match self.fit() {
+            Some((origin, intercept, slope)) => {
+                let offset = intercept + slope * (world_time - origin) as f64;
+                (world_time as f64 + offset).round() as i64
+            }
+            None => world_time + ZWIFT_EPOCH_MS,
+        }
+    }
+
+    // parts per million the world clock runs slow against capture clock
+    pub fn drift(&self) -> Option<f64> {
+        if self.samples.len() < 2 {
+            return None;
+        }
+        self.fit().map(|(_, _, slope)| slope * 1e6)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::clock::WorldClock;
+    use crate::fit::ZWIFT_EPOCH_MS;
+
+    #[test]
+    fn fallback_epoch() {
+        let clock = WorldClock::default();
+        assert_eq!(clock.to_utc(1_000), ZWIFT_EPOCH_MS + 1_000);
+        assert_eq!(clock.drift(), None);
+    }
+
+    #[test]
+    fn estimate_drift() {
+        let mut clock = WorldClock::default();
+        let epoch = 1_600_000_000_000i64;
+        for second in 0..300 {
+            let world_time = 200_000_000_000 + second * 1_000;
+            // 50 ppm drift
+            let utc = epoch + second * 1_000 + second / 20;
+            // delayed duplicate in the same second is ignored
+            clock.observe(world_time + 100, (utc + 100 + 40) * 1000);
+            clock.observe(world_time, utc * 1000);
+        }
+        assert!((clock.drift().unwrap() - 50.).abs() < 1.);
+        let world_time = 200_000_000_000 + 400_000;
+        assert!((clock.to_utc(world_time) - (epoch + 400_020)).abs() <= 1);
+    }
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+// client datagrams end with 4 bytes, looks like truncated MAC
+pub const TRAILER_LEN: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatagramError {
+    Empty,
+    TooShort(usize),
+    InvalidHeaderLength {
+        header_length: usize,
+        payload_length: usize,
+    },
+    MalformedHeader,
+}
+
+impl fmt::Display for DatagramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatagramError::Empty => write!(f, "empty datagram"),
+            DatagramError::TooShort(length) => write!(f, "datagram too short: {} bytes", length),
+            DatagramError::InvalidHeaderLength {
+                header_length,
+                payload_length,
+            } => write!(
+                f,
+                "header length {} does not fit datagram of {} bytes",
+                header_length, payload_length
+            ),
+            DatagramError::MalformedHeader => write!(f, "malformed datagram header"),
+        }
+    }
+}
+
+impl Error for DatagramError {}
+
+// client to server udp datagram layout:
+// [header length + 1][connection id varint][sequence varint][protobuf body][4 bytes trailer]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClientDatagramHeader {
+    pub header_length: usize, // bytes before protobuf body
+    pub connection_id: u64,
+    pub sequence: u64,
+    pub trailer: [u8; TRAILER_LEN],
+}
+
+fn read_varint(bytes: &[u8]) -> Result<(u64, usize), DatagramError> {
+    let mut value: u64 = 0;
+    for (ix, &byte) in bytes.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * ix);
+        if byte & 0x80 == 0 {
+            return Ok((value, ix + 1));
+        }
+    }
+    Err(DatagramError::MalformedHeader)
+}
+
+impl ClientDatagramHeader {
+    // returns header and protobuf body of ClientToServer message
+    pub fn parse(payload: &[u8]) -> Result<(Self, &[u8]), DatagramError> {
+        let first = *payload.first().ok_or(DatagramError::Empty)?;
+        if payload.len() < 1 + TRAILER_LEN {
+            return Err(DatagramError::TooShort(payload.len()));
+        }
+        let header_length = (first as usize).saturating_sub(1);
+        let limit = payload.len() - TRAILER_LEN;
+        if header_length == 0 || header_length > limit {
+            return Err(DatagramError::InvalidHeaderLength {
+                header_length,
+                payload_length: payload.len(),
+            });
+        }
+
+        let header = &payload[1..header_length];
+        let (connection_id, read) = if header.is_empty() {
+            (0, 0)
+        } else {
+            read_varint(header)?
+        };
+        let (sequence, _) = if header.len() > read {
+            read_varint(&header[read..])?
+        } else {
+            (0, 0)
+        };
+
+        let mut trailer = [0; TRAILER_LEN];
+        trailer.copy_from_slice(&payload[limit..]);
+        Ok((
+            ClientDatagramHeader {
+                header_length,
+                connection_id,
+                sequence,
+                trailer,
+            },
+            &payload[header_length..limit],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::datagram::{ClientDatagramHeader, DatagramError};
+    use hex_literal::hex;
+
+    #[test]
+    fn parse_header() {
+        let packet_payload = hex!("0686a9010008011086d30618e1a6fbcce80520ab023a6e0886d30610e1a6fbcce8051800208fac3a2800300040f4fa860548005000584f600068cbd5aa0170c0843d7800800100980195809808a0018f808008a80100b80100c00100cd01ae378847d50119191a46dd01a0d52ec7e00186d306e80100f80100950200000000980206b002001f403176");
+        let (header, body) = ClientDatagramHeader::parse(&packet_payload).unwrap();
+        assert_eq!(header.header_length, 5);
+        assert_eq!(header.connection_id, 21638);
+        assert_eq!(header.sequence, 0);
+        assert_eq!(header.trailer, hex!("1f403176"));
+        assert_eq!(body[0], 0x08);
+        assert_eq!(body.len(), packet_payload.len() - 9);
+    }
+
+    #[test]
+    fn invalid_datagrams() {
+        assert_eq!(ClientDatagramHeader::parse(&[]), Err(DatagramError::Empty));
+        assert_eq!(
+            ClientDatagramHeader::parse(&[0x06, 0x00]),
+            Err(DatagramError::TooShort(2))
+        );
+        assert_eq!(
+            ClientDatagramHeader::parse(&[0x20, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            Err(DatagramError::InvalidHeaderLength {
+                header_length: 31,
+                payload_length: 6
+            })
+        );
+    }
+}
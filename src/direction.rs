@@ -0,0 +1,201 @@
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+
+use protobuf::Message;
+
+use crate::datagram::ClientDatagramHeader;
+use crate::session::SessionInfo;
+use crate::zwift_messages::{ClientToServer, ServerToClient};
+use crate::{Direction, TCP_PORT, UDP_PORT};
+
+// ports the zwift servers listen on
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerPorts {
+    pub udp: Vec<RangeInclusive<u16>>,
+    pub tcp: Vec<RangeInclusive<u16>>,
+}
+
+impl Default for ServerPorts {
+    fn default() -> Self {
+        ServerPorts {
+            udp: vec![UDP_PORT..=UDP_PORT],
+            tcp: vec![TCP_PORT..=TCP_PORT],
+        }
+    }
+}
+
+fn bpf_ports(protocol: &str, ranges: &[RangeInclusive<u16>]) -> Vec<String> {
+    ranges
+        .iter()
+        .map(|range| {
+            if range.start() == range.end() {
+                format!("{} port {}", protocol, range.start())
+            } else {
+                format!("{} portrange {}-{}", protocol, range.start(), range.end())
+            }
+        })
+        .collect()
+}
+
+impl ServerPorts {
+    pub fn new(udp: Vec<RangeInclusive<u16>>, tcp: Vec<RangeInclusive<u16>>) -> Self {
+        ServerPorts { udp, tcp }
+    }
+
+    pub fn is_udp(&self, port: u16) -> bool {
+        self.udp.iter().any(|range| range.contains(&port))
+    }
+
+    pub fn is_tcp(&self, port: u16) -> bool {
+        self.tcp.iter().any(|range| range.contains(&port))
+    }
+
+    // bpf expression capturing traffic on the ports
+    pub fn filter(&self) -> String {
+        let mut clauses = bpf_ports("udp", &self.udp);
+        clauses.extend(bpf_ports("tcp", &self.tcp));
+        clauses.join(" or ")
+    }
+
+    // ports filter narrowed by every extra bpf expression
+    pub fn filter_with(&self, extra: &[String]) -> String {
+        self.filter_for(&BTreeSet::new(), extra)
+    }
+
+    // like filter_with, also udp of known servers on any port
+    pub fn filter_for(&self, servers: &BTreeSet<IpAddr>, extra: &[String]) -> String {
+        let mut filter = self.filter();
+        if !servers.is_empty() {
+            let hosts: Vec<_> = servers.iter().map(|ip| format!("host {}", ip)).collect();
+            filter = format!("{} or (udp and ({}))", filter, hosts.join(" or "));
+        }
+        if extra.is_empty() {
+            return filter;
+        }
+        let clauses: Vec<_> = std::iter::once(filter)
+            .chain(extra.iter().cloned())
+            .map(|clause| format!("({})", clause))
+            .collect();
+        clauses.join(" and ")
+    }
+}
+
+// client datagrams have a header before the protobuf body, server datagrams are bare protobuf
+pub fn message_shape(payload: &[u8]) -> Option<Direction> {
+    let to_server = match ClientDatagramHeader::parse(payload) {
+        Ok((_, body)) => match ClientToServer::parse_from_bytes(body) {
+            Ok(message) => message.get_rider_id() != 0 && message.get_world_time() > 0,
+            Err(_) => false,
+        },
+        Err(_) => false,
+    };
+    let from_server = match ServerToClient::parse_from_bytes(payload) {
+        Ok(message) => message.get_world_time() > 0,
+        Err(_) => false,
+    };
+    match (to_server, from_server) {
+        (true, false) => Some(Direction::ToServer),
+        (false, true) => Some(Direction::FromServer),
+        _ => None,
+    }
+}
+
+// direction of a udp datagram, from the most to the least reliable hint
+//   servers learned in the session, one side on a server port, message shape,
+//   finally the source port alone
+pub fn classify(
+    session: &SessionInfo,
+    ports: &ServerPorts,
+    source: SocketAddr,
+    destination: SocketAddr,
+    payload: &[u8],
+) -> Direction {
+    if let Some(direction) = session.direction(source.ip(), destination.ip()) {
+        return direction;
+    }
+    match (
+        ports.is_udp(source.port()),
+        ports.is_udp(destination.port()),
+    ) {
+        (true, false) => return Direction::FromServer,
+        (false, true) => return Direction::ToServer,
+        _ => {}
+    }
+    match message_shape(payload) {
+        Some(direction) => direction,
+        None if ports.is_udp(source.port()) => Direction::FromServer,
+        None => Direction::ToServer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::BTreeSet;
+    use std::net::{IpAddr, SocketAddr};
+
+    use hex_literal::hex;
+    use protobuf::Message;
+
+    use crate::direction::{classify, message_shape, ServerPorts};
+    use crate::session::SessionInfo;
+    use crate::zwift_messages::ServerToClient;
+    use crate::{Direction, CAPTURE_FILTER};
+
+    #[test]
+    fn server_ports() {
+        let ports = ServerPorts::default();
+        assert_eq!(ports.filter(), CAPTURE_FILTER);
+        assert!(ports.is_udp(3022) && !ports.is_udp(3023));
+
+        let ports = ServerPorts::new(vec![3022..=3022, 3100..=3110], vec![3023..=3023]);
+        assert!(ports.is_udp(3105));
+        assert_eq!(
+            ports.filter(),
+            "udp port 3022 or udp portrange 3100-3110 or tcp port 3023"
+        );
+
+        let servers: BTreeSet<IpAddr> = ["52.1.2.3", "52.1.2.4"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        assert_eq!(
+            ServerPorts::default().filter_for(&servers, &["not port 53".to_string()]),
+            "(udp port 3022 or tcp port 3023 or (udp and (host 52.1.2.3 or host 52.1.2.4))) \
+             and (not port 53)"
+        );
+    }
+
+    #[test]
+    fn classify_by_shape() {
+        let to_server = hex!("0686a9010008011086d30618e1a6fbcce80520ab023a6e0886d30610e1a6fbcce8051800208fac3a2800300040f4fa860548005000584f600068cbd5aa0170c0843d7800800100980195809808a0018f808008a80100b80100c00100cd01ae378847d50119191a46dd01a0d52ec7e00186d306e80100f80100950200000000980206b002001f403176");
+        let mut message = ServerToClient::new();
+        message.set_rider_id(108934);
+        message.set_world_time(200_000_000_000);
+        let from_server = message.write_to_bytes().unwrap();
+        assert_eq!(message_shape(&to_server), Some(Direction::ToServer));
+        assert_eq!(message_shape(&from_server), Some(Direction::FromServer));
+
+        // client also on the server port, only the shape tells
+        let client: SocketAddr = "192.168.1.20:3022".parse().unwrap();
+        let server: SocketAddr = "52.1.2.3:3022".parse().unwrap();
+        let session = SessionInfo::new();
+        let ports = ServerPorts::default();
+        assert_eq!(
+            classify(&session, &ports, client, server, &to_server),
+            Direction::ToServer
+        );
+        assert_eq!(
+            classify(&session, &ports, server, client, &from_server),
+            Direction::FromServer
+        );
+        // server on a port not configured
+        let server: SocketAddr = "52.1.2.3:3100".parse().unwrap();
+        let client: SocketAddr = "192.168.1.20:50000".parse().unwrap();
+        assert_eq!(
+            classify(&session, &ports, client, server, &to_server),
+            Direction::ToServer
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::datagram::DatagramError;
+
+#[derive(Debug)]
+pub enum ZwiftCaptureError {
+    // capture device or file errors
+    Pcap(pcap::Error),
+    // link, ip or transport headers could not be parsed
+    Packet(etherparse::ReadError),
+    UnsupportedLinkType(i32),
+    UnsupportedEtherType(u16),
+    // 802.11 management, control or encrypted frame
+    NotDataFrame,
+    TruncatedLinkHeader,
+    Datagram(DatagramError),
+    Protobuf(protobuf::ProtobufError),
+    // message is not of the expected direction
+    UnexpectedMessage,
+    DeviceNotFound {
+        name: String,
+        available: Vec<String>,
+    },
+}
+
+impl ZwiftCaptureError {
+    // short stable name, e.g. for metric labels
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ZwiftCaptureError::Pcap(_) => "pcap",
+            ZwiftCaptureError::Packet(_) => "packet",
+            ZwiftCaptureError::UnsupportedLinkType(_) => "unsupported_link_type",
+            ZwiftCaptureError::UnsupportedEtherType(_) => "unsupported_ether_type",
+            ZwiftCaptureError::NotDataFrame => "not_data_frame",
+            ZwiftCaptureError::TruncatedLinkHeader => "truncated_link_header",
+            ZwiftCaptureError::Datagram(_) => "datagram",
+            ZwiftCaptureError::Protobuf(_) => "protobuf",
+            ZwiftCaptureError::UnexpectedMessage => "unexpected_message",
+            ZwiftCaptureError::DeviceNotFound { .. } => "device_not_found",
+        }
+    }
+}
+
+impl fmt::Display for ZwiftCaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ZwiftCaptureError::Pcap(error) => write!(f, "capture error: {}", error),
+            ZwiftCaptureError::Packet(error) => write!(f, "failed to parse packet: {:?}", error),
+            ZwiftCaptureError::UnsupportedLinkType(linktype) => {
+                write!(f, "unsupported link type: {}", linktype)
+            }
+            ZwiftCaptureError::UnsupportedEtherType(ethertype) => {
+                write!(f, "unsupported ether type: {:#06x}", ethertype)
+            }
+            ZwiftCaptureError::NotDataFrame => write!(f, "802.11 frame without ip data"),
+            ZwiftCaptureError::TruncatedLinkHeader => write!(f, "truncated link header"),
+            ZwiftCaptureError::Datagram(error) => write!(f, "invalid datagram: {}", error),
+            ZwiftCaptureError::Protobuf(error) => write!(f, "failed to decode message: {}", error),
+            ZwiftCaptureError::UnexpectedMessage => write!(f, "unexpected message"),
+            ZwiftCaptureError::DeviceNotFound { name, available } => write!(
+                f,
+                "no capture device {}, available: {}",
+                name,
+                available.join(", ")
+            ),
+        }
+    }
+}
+
+impl Error for ZwiftCaptureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ZwiftCaptureError::Pcap(error) => Some(error),
+            ZwiftCaptureError::Datagram(error) => Some(error),
+            ZwiftCaptureError::Protobuf(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<pcap::Error> for ZwiftCaptureError {
+    fn from(error: pcap::Error) -> Self {
+        ZwiftCaptureError::Pcap(error)
+    }
+}
+
+impl From<etherparse::ReadError> for ZwiftCaptureError {
+    fn from(error: etherparse::ReadError) -> Self {
+        ZwiftCaptureError::Packet(error)
+    }
+}
+
+impl From<DatagramError> for ZwiftCaptureError {
+    fn from(error: DatagramError) -> Self {
+        ZwiftCaptureError::Datagram(error)
+    }
+}
+
+impl From<protobuf::ProtobufError> for ZwiftCaptureError {
+    fn from(error: protobuf::ProtobufError) -> Self {
+        ZwiftCaptureError::Protobuf(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ZwiftCaptureError>;
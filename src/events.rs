@@ -0,0 +1,148 @@
+use protobuf::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::zwift_messages::{Chat, Payload105, PlayerUpdate, RideOn, ServerToClient, TimeSync};
+
+// PlayerUpdate.tag3 values
+pub const KIND_TIME_SYNC: i32 = 3;
+pub const KIND_RIDE_ON: i32 = 4;
+pub const KIND_CHAT: i32 = 5;
+pub const KIND_RIDER_ENTERED_WORLD: i32 = 105;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    Chat {
+        rider_id: i32,
+        to_rider_id: i32, // 0 if public message
+        first_name: String,
+        last_name: String,
+        message: String,
+        avatar: String,
+        country_code: i32,
+        event_subgroup: i32,
+    },
+    RideOn {
+        rider_id: i32,
+        to_rider_id: i32,
+        first_name: String,
+        last_name: String,
+        country_code: i32,
+    },
+    RiderEnteredWorld {
+        rider_id: i32,
+        first_name: String,
+        last_name: String,
+    },
+    TimeSync {
+        rider_id: i32,
+        world_time: i64, // millis
+    },
+    // payloads with unknown or undecodable format
+    Unknown {
+        kind: i32,
+        bytes: Vec<u8>,
+    },
+}
+
+impl GameEvent {
+    pub fn from(update: &PlayerUpdate) -> Self {
+        let kind = update.get_tag3();
+        let payload = update.get_payload();
+        let event = match kind {
+            KIND_CHAT => Chat::parse_from_bytes(payload)
+                .ok()
+                .map(|chat| GameEvent::Chat {
+                    rider_id: chat.get_rider_id(),
+                    to_rider_id: chat.get_to_rider_id(),
+                    first_name: chat.get_firstName().to_string(),
+                    last_name: chat.get_lastName().to_string(),
+                    message: chat.get_message().to_string(),
+                    avatar: chat.get_avatar().to_string(),
+                    country_code: chat.get_countryCode(),
+                    event_subgroup: chat.get_eventSubgroup(),
+                }),
+            KIND_RIDE_ON => {
+                RideOn::parse_from_bytes(payload)
+                    .ok()
+                    .map(|ride_on| GameEvent::RideOn {
+                        rider_id: ride_on.get_rider_id(),
+                        to_rider_id: ride_on.get_to_rider_id(),
+                        first_name: ride_on.get_firstName().to_string(),
+                        last_name: ride_on.get_lastName().to_string(),
+                        country_code: ride_on.get_countryCode(),
+                    })
+            }
+            KIND_RIDER_ENTERED_WORLD => Payload105::parse_from_bytes(payload).ok().map(|rider| {
+                GameEvent::RiderEnteredWorld {
+                    rider_id: rider.get_rider_id(),
+                    first_name: rider.get_firstName().to_string(),
+                    last_name: rider.get_lastName().to_string(),
+                }
+            }),
+            KIND_TIME_SYNC => {
+                TimeSync::parse_from_bytes(payload)
+                    .ok()
+                    .map(|time_sync| GameEvent::TimeSync {
+                        rider_id: time_sync.get_rider_id(),
+                        world_time: time_sync.get_world_time(),
+                    })
+            }
+            _ => None,
+        };
+        event.unwrap_or_else(|| GameEvent::Unknown {
+            kind,
+            bytes: payload.to_vec(),
+        })
+    }
+
+    pub fn from_message(message: &ServerToClient) -> Vec<Self> {
+        message
+            .player_updates
+            .iter()
+            .map(|update| GameEvent::from(update))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::events::{GameEvent, KIND_CHAT};
+    use crate::zwift_messages::{Chat, PlayerUpdate};
+    use protobuf::Message;
+
+    #[test]
+    fn decode_chat() {
+        let mut chat = Chat::new();
+        chat.set_rider_id(108934);
+        chat.set_firstName("Maks".to_string());
+        chat.set_message("ride on!".to_string());
+        let mut update = PlayerUpdate::new();
+        update.set_tag3(KIND_CHAT);
+        update.set_payload(chat.write_to_bytes().unwrap());
+
+        match GameEvent::from(&update) {
+            GameEvent::Chat {
+                rider_id, message, ..
+            } => {
+                assert_eq!(rider_id, 108934);
+                assert_eq!(message, "ride on!");
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn unknown_payload() {
+        let mut update = PlayerUpdate::new();
+        update.set_tag3(110);
+        update.set_payload(vec![0x08, 0x01]);
+        assert_eq!(
+            GameEvent::from(&update),
+            GameEvent::Unknown {
+                kind: 110,
+                bytes: vec![0x08, 0x01]
+            }
+        );
+    }
+}
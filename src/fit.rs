@@ -0,0 +1,512 @@
+use std::io::{self, Write};
+
+use crate::clock::WorldClock;
+use crate::geo::World;
+use crate::{Event, Player, Sport};
+
+// zwift world time is millis since this unix time
+pub const ZWIFT_EPOCH_MS: i64 = 1_414_016_074_400;
+// fit timestamps are seconds since 1989-12-31 00:00:00 UTC
+const FIT_EPOCH_S: i64 = 631_065_600;
+
+const PROTOCOL_VERSION: u8 = 0x10;
+const PROFILE_VERSION: u16 = 2132;
+const HEADER_SIZE: u8 = 14;
+
+// global message numbers
+const MESG_FILE_ID: u16 = 0;
+const MESG_SESSION: u16 = 18;
+const MESG_LAP: u16 = 19;
+const MESG_RECORD: u16 = 20;
+const MESG_EVENT: u16 = 21;
+const MESG_ACTIVITY: u16 = 34;
+
+// base types
+const ENUM: u8 = 0x00;
+const UINT8: u8 = 0x02;
+const UINT16: u8 = 0x84;
+const UINT32: u8 = 0x86;
+const SINT32: u8 = 0x85;
+const UINT32Z: u8 = 0x8c;
+
+const MANUFACTURER_ZWIFT: u16 = 260;
+const FILE_ACTIVITY: u8 = 4;
+const SPORT_GENERIC: u8 = 0;
+const SPORT_RUNNING: u8 = 1;
+const SPORT_CYCLING: u8 = 2;
+const SUB_SPORT_VIRTUAL_ACTIVITY: u8 = 58;
+const EVENT_TIMER: u8 = 0;
+const EVENT_LAP: u8 = 9;
+const EVENT_SESSION: u8 = 8;
+const EVENT_ACTIVITY: u8 = 26;
+const EVENT_TYPE_START: u8 = 0;
+const EVENT_TYPE_STOP: u8 = 1;
+const EVENT_TYPE_STOP_ALL: u8 = 4;
+
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xcc01, 0xd801, 0x1400, 0xf001, 0x3c00, 0x2800, 0xe401, 0xa001, 0x6c00, 0x7800, 0xb401,
+    0x5000, 0x9c01, 0x8801, 0x4400,
+];
+
+pub fn crc(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        let mut crc = crc;
+        let tmp = CRC_TABLE[(crc & 0xf) as usize];
+        crc = (crc >> 4) & 0x0fff;
+        crc = crc ^ tmp ^ CRC_TABLE[(byte & 0xf) as usize];
+        let tmp = CRC_TABLE[(crc & 0xf) as usize];
+        crc = (crc >> 4) & 0x0fff;
+        crc ^ tmp ^ CRC_TABLE[((byte >> 4) & 0xf) as usize]
+    })
+}
+
+pub fn world_time_to_fit(world_time: i64) -> u32 {
+    utc_to_fit(world_time + ZWIFT_EPOCH_MS)
+}
+
+// millis since unix epoch
+pub fn utc_to_fit(utc: i64) -> u32 {
+    (utc / 1000 - FIT_EPOCH_S) as u32
+}
+
+// low level writer of definition and data messages
+#[derive(Default)]
+pub struct FitEncoder {
+    data: Vec<u8>,
+}
+
+impl FitEncoder {
+    pub fn new() -> Self {
+        FitEncoder::default()
+    }
+
+    // fields are (field number, size, base type)
+    pub fn define(&mut self, local_type: u8, global_type: u16, fields: &[(u8, u8, u8)]) {
+        self.data.push(0x40 | (local_type & 0xf));
+        self.data.push(0); // reserved
+        self.data.push(0); // little endian
+        self.data.extend_from_slice(&global_type.to_le_bytes());
+        self.data.push(fields.len() as u8);
+        for &(number, size, base_type) in fields {
+            self.data.extend_from_slice(&[number, size, base_type]);
+        }
+    }
+
+    pub fn begin(&mut self, local_type: u8) -> &mut Self {
+        self.data.push(local_type & 0xf);
+        self
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.data.push(value);
+        self
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    // complete file with header and crc
+    pub fn finish(self) -> Vec<u8> {
+        let mut file = Vec::with_capacity(self.data.len() + HEADER_SIZE as usize + 2);
+        file.push(HEADER_SIZE);
+        file.push(PROTOCOL_VERSION);
+        file.extend_from_slice(&PROFILE_VERSION.to_le_bytes());
+        file.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        file.extend_from_slice(b".FIT");
+        let header_crc = crc(&file);
+        file.extend_from_slice(&header_crc.to_le_bytes());
+        file.extend_from_slice(&self.data);
+        let file_crc = crc(&file);
+        file.extend_from_slice(&file_crc.to_le_bytes());
+        file
+    }
+}
+
+// degrees to semicircles
+fn fit_sport(sport: Sport) -> u8 {
+    match sport {
+        Sport::Cycling => SPORT_CYCLING,
+        Sport::Running => SPORT_RUNNING,
+        Sport::Unknown(_) => SPORT_GENERIC,
+    }
+}
+
+fn semicircles(degrees: f64) -> u32 {
+    (degrees * (2f64.powi(31) / 180.)) as i32 as u32
+}
+
+fn clamp_u8(value: f64) -> u8 {
+    if value.is_finite() && value >= 0. {
+        value.min(254.) as u8
+    } else {
+        0xff
+    }
+}
+
+fn clamp_u16(value: f64) -> u16 {
+    if value.is_finite() && value >= 0. {
+        value.min(65534.) as u16
+    } else {
+        0xffff
+    }
+}
+
+fn clamp_u32(value: f64) -> u32 {
+    if value.is_finite() && value >= 0. {
+        value.min(4_294_967_294.) as u32
+    } else {
+        0xffff_ffff
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FitRecord {
+    pub timestamp: u32,               // fit seconds
+    pub power: i32,                   // watts
+    pub cadence: i32,                 // rpm
+    pub heartrate: i32,               // bpm
+    pub speed: f64,                   // m per sec
+    pub distance: f64,                // m
+    pub altitude: Option<f64>,        // m
+    pub position: Option<(f64, f64)>, // lat, lon
+    pub lap: i32,
+}
+
+impl FitRecord {
+    pub fn from(player: &Player) -> Self {
+        FitRecord {
+            timestamp: world_time_to_fit(player.world_time),
+            power: player.power,
+            cadence: player.cadence,
+            heartrate: player.heartrate,
+            speed: player.speed,
+            distance: player.distance as f64,
+            altitude: Some(player.altitude),
+            position: None,
+            lap: player.laps,
+        }
+    }
+}
+
+// activity of a single observed rider
+pub struct FitActivity {
+    pub rider_id: i32,
+    // world for gps positions, None to follow the rider's course
+    pub world: Option<World>,
+    // record timestamps, fed by ingest
+    pub clock: WorldClock,
+    // of the latest player added
+    pub sport: Sport,
+    records: Vec<FitRecord>,
+}
+
+impl FitActivity {
+    pub fn new(rider_id: i32) -> Self {
+        FitActivity {
+            rider_id,
+            world: None,
+            clock: WorldClock::default(),
+            sport: Sport::Cycling,
+            records: vec![],
+        }
+    }
+
+    pub fn with_world(rider_id: i32, world: World) -> Self {
+        FitActivity {
+            world: Some(world),
+            ..FitActivity::new(rider_id)
+        }
+    }
+
+    // players of other riders and repeated timestamps are ignored
+    pub fn add(&mut self, player: &Player) {
+        if player.id != self.rider_id {
+            return;
+        }
+        self.sport = player.sport;
+        let mut record = FitRecord::from(player);
+        record.timestamp = utc_to_fit(self.clock.to_utc(player.world_time));
+        let world = self
+            .world
+            .or_else(|| World::from_course(player.flags.course));
+        if let Some(world) = world {
+            let position = world.player_position(player);
+            record.position = Some((position.lat, position.lon));
+        }
+        self.add_record(record);
+    }
+
+    pub fn add_record(&mut self, record: FitRecord) {
+        match self.records.last() {
+            Some(last) if last.timestamp >= record.timestamp => {}
+            _ => self.records.push(record),
+        }
+    }
+
+    pub fn ingest(&mut self, event: &Event) {
+        self.clock.ingest(event);
+        for player in &event.players {
+            self.add(player);
+        }
+    }
+
+    pub fn records(&self) -> &[FitRecord] {
+        &self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn write_lap(encoder: &mut FitEncoder, first: &FitRecord, last: &FitRecord) {
+        let elapsed = (last.timestamp - first.timestamp) as f64 * 1000.;
+        encoder
+            .begin(2)
+            .u32(last.timestamp)
+            .u32(first.timestamp)
+            .u32(clamp_u32(elapsed))
+            .u32(clamp_u32(elapsed))
+            .u32(clamp_u32((last.distance - first.distance) * 100.))
+            .u8(EVENT_LAP)
+            .u8(EVENT_TYPE_STOP);
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = FitEncoder::new();
+        let (first, last) = match (self.records.first(), self.records.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return encoder.finish(),
+        };
+
+        encoder.define(
+            0,
+            MESG_FILE_ID,
+            &[
+                (0, 1, ENUM),    // type
+                (1, 2, UINT16),  // manufacturer
+                (2, 2, UINT16),  // product
+                (3, 4, UINT32Z), // serial number
+                (4, 4, UINT32),  // time created
+            ],
+        );
+        encoder
+            .begin(0)
+            .u8(FILE_ACTIVITY)
+            .u16(MANUFACTURER_ZWIFT)
+            .u16(0)
+            .u32(self.rider_id as u32)
+            .u32(first.timestamp);
+
+        encoder.define(
+            3,
+            MESG_EVENT,
+            &[
+                (253, 4, UINT32), // timestamp
+                (0, 1, ENUM),     // event
+                (1, 1, ENUM),     // event type
+            ],
+        );
+        encoder
+            .begin(3)
+            .u32(first.timestamp)
+            .u8(EVENT_TIMER)
+            .u8(EVENT_TYPE_START);
+
+        encoder.define(
+            1,
+            MESG_RECORD,
+            &[
+                (253, 4, UINT32), // timestamp
+                (0, 4, SINT32),   // position lat, semicircles
+                (1, 4, SINT32),   // position long, semicircles
+                (2, 2, UINT16),   // altitude, 5 * (m + 500)
+                (3, 1, UINT8),    // heart rate
+                (4, 1, UINT8),    // cadence
+                (5, 4, UINT32),   // distance, cm
+                (6, 2, UINT16),   // speed, mm per sec
+                (7, 2, UINT16),   // power
+            ],
+        );
+        encoder.define(
+            2,
+            MESG_LAP,
+            &[
+                (253, 4, UINT32), // timestamp
+                (2, 4, UINT32),   // start time
+                (7, 4, UINT32),   // total elapsed time, ms
+                (8, 4, UINT32),   // total timer time, ms
+                (9, 4, UINT32),   // total distance, cm
+                (0, 1, ENUM),     // event
+                (1, 1, ENUM),     // event type
+            ],
+        );
+
+        let mut lap_start = first;
+        let mut num_laps: u16 = 0;
+        for record in &self.records {
+            if record.lap != lap_start.lap {
+                FitActivity::write_lap(&mut encoder, lap_start, record);
+                lap_start = record;
+                num_laps += 1;
+            }
+            let (lat, lon) = match record.position {
+                Some((lat, lon)) => (semicircles(lat), semicircles(lon)),
+                None => (0x7fff_ffff, 0x7fff_ffff),
+            };
+            encoder
+                .begin(1)
+                .u32(record.timestamp)
+                .u32(lat)
+                .u32(lon)
+                .u16(match record.altitude {
+                    Some(altitude) => clamp_u16((altitude + 500.) * 5.),
+                    None => 0xffff,
+                })
+                .u8(clamp_u8(record.heartrate as f64))
+                .u8(clamp_u8(record.cadence as f64))
+                .u32(clamp_u32(record.distance * 100.))
+                .u16(clamp_u16(record.speed * 1000.))
+                .u16(clamp_u16(record.power as f64));
+        }
+        if lap_start.timestamp < last.timestamp || num_laps == 0 {
+            FitActivity::write_lap(&mut encoder, lap_start, last);
+            num_laps += 1;
+        }
+
+        encoder
+            .begin(3)
+            .u32(last.timestamp)
+            .u8(EVENT_TIMER)
+            .u8(EVENT_TYPE_STOP_ALL);
+
+        let elapsed = clamp_u32((last.timestamp - first.timestamp) as f64 * 1000.);
+        encoder.define(
+            4,
+            MESG_SESSION,
+            &[
+                (253, 4, UINT32), // timestamp
+                (2, 4, UINT32),   // start time
+                (7, 4, UINT32),   // total elapsed time, ms
+                (8, 4, UINT32),   // total timer time, ms
+                (9, 4, UINT32),   // total distance, cm
+                (5, 1, ENUM),     // sport
+                (6, 1, ENUM),     // sub sport
+                (25, 2, UINT16),  // first lap index
+                (26, 2, UINT16),  // num laps
+                (0, 1, ENUM),     // event
+                (1, 1, ENUM),     // event type
+            ],
+        );
+        encoder
+            .begin(4)
+            .u32(last.timestamp)
+            .u32(first.timestamp)
+            .u32(elapsed)
+            .u32(elapsed)
+            .u32(clamp_u32((last.distance - first.distance) * 100.))
+            .u8(fit_sport(self.sport))
+            .u8(SUB_SPORT_VIRTUAL_ACTIVITY)
+            .u16(0)
+            .u16(num_laps)
+            .u8(EVENT_SESSION)
+            .u8(EVENT_TYPE_STOP);
+
+        encoder.define(
+            5,
+            MESG_ACTIVITY,
+            &[
+                (253, 4, UINT32), // timestamp
+                (0, 4, UINT32),   // total timer time, ms
+                (1, 2, UINT16),   // num sessions
+                (2, 1, ENUM),     // type, manual
+                (3, 1, ENUM),     // event
+                (4, 1, ENUM),     // event type
+            ],
+        );
+        encoder
+            .begin(5)
+            .u32(last.timestamp)
+            .u32(elapsed)
+            .u16(1)
+            .u8(0)
+            .u8(EVENT_ACTIVITY)
+            .u8(EVENT_TYPE_STOP);
+
+        encoder.finish()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::fit::{crc, fit_sport, FitActivity, FitRecord};
+    use crate::fixtures::FROM_SERVER;
+    use crate::{Sport, ZwiftMessage};
+
+    fn record(timestamp: u32, lap: i32) -> FitRecord {
+        FitRecord {
+            timestamp,
+            power: 250,
+            cadence: 90,
+            heartrate: 150,
+            speed: 10.,
+            distance: timestamp as f64 * 10.,
+            altitude: Some(12.5),
+            position: Some((-11.6449, 166.9529)),
+            lap,
+        }
+    }
+
+    #[test]
+    fn encode_activity() {
+        let mut activity = FitActivity::new(108934);
+        for timestamp in 1_000_000..1_000_010 {
+            activity.add_record(record(timestamp, (timestamp >= 1_000_005) as i32));
+        }
+        // repeated sample is ignored
+        activity.add_record(record(1_000_009, 1));
+        assert_eq!(activity.len(), 10);
+
+        let file = activity.encode();
+        assert_eq!(&file[8..12], b".FIT");
+        let data_size = u32::from_le_bytes([file[4], file[5], file[6], file[7]]) as usize;
+        assert_eq!(file.len(), 14 + data_size + 2);
+        assert_eq!(crc(&file[..12]), u16::from_le_bytes([file[12], file[13]]));
+        // crc over whole file including crc is zero
+        assert_eq!(crc(&file), 0);
+    }
+
+    #[test]
+    fn position_and_sport_from_player() {
+        let players = ZwiftMessage::FromServer(&FROM_SERVER)
+            .get_players()
+            .unwrap();
+        let mut activity = FitActivity::new(players[0].id);
+        activity.add(&players[0]);
+        // course 6 is watopia, south of the equator
+        let (lat, lon) = activity.records()[0].position.unwrap();
+        assert!(lat < -11. && lat > -12.5);
+        assert!(lon > 166. && lon < 168.);
+        assert_eq!(activity.sport, Sport::Cycling);
+
+        let mut activity = FitActivity::new(players[1].id);
+        activity.add(&players[1]);
+        assert_eq!(activity.sport, Sport::Running);
+        assert_eq!(fit_sport(activity.sport), 1);
+    }
+}
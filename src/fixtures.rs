@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hex_literal::hex;
+
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+// captured ServerToClient udp payload with a cyclist, a runner and a cyclist
+pub const FROM_SERVER: [u8; 406] = hex!("08011086d30618d5a3fbcce80520ca154273089dc630109da2fbcce805184220af993a280030d0d0ea0a4096adfd0448e1e13250005800602268b2c9a40170c3a13d780080010f9801958018a0018f808010a80100b80100c001a801cd01ab4a8247d501066f1c46dd01376f34c7e0019dc630e80100f801009502016ccb45980206b00201428b0108c8c1de0110caa2fbcce805188f1020ee923a280030f0f6df0440ec96c60448abeeab01500058a501600068adece1ffffffffffff017090dd3c78018001bd06980190809810a0018f808008a80180a201b001e4cdc8cce805b80100c001b08c01cd0190568147d501be411d46dd01615a39c7e001c8c1de01e80100f801019502c2074a48980206b00200427808fdcdae0110e3a2fbcce805189c06208f8e3a28003098a6a80940c68ad00448fef131500358626088016896a6df0270deee3c780480017f9801918018a0018f808010a801800cb80100c001bc1fcd01e00a8047d501ecf51d46dd012b173ac7e001fdcdae01e80100f801009502774b9a47980206b0020088017f900101980101");
+
+// pcap file with ethernet/ipv4/udp packets from the game server 10.0.0.1:3022 at given seconds
+fn capture_file(payload: &[u8], seconds: &[u32]) -> Vec<u8> {
+    let udp_length = 8 + payload.len();
+    let ip_length = 20 + udp_length;
+    let mut file = vec![];
+    file.extend(&hex!(
+        "d4c3b2a1 0200 0400 00000000 00000000 ffff0000 01000000"
+    ));
+    for second in seconds {
+        file.extend(&second.to_le_bytes());
+        file.extend(&0u32.to_le_bytes());
+        file.extend(&((14 + ip_length) as u32).to_le_bytes());
+        file.extend(&((14 + ip_length) as u32).to_le_bytes());
+        file.extend(&hex!("020000000001 020000000002 0800"));
+        file.extend(&hex!("4500"));
+        file.extend(&(ip_length as u16).to_be_bytes());
+        file.extend(&hex!("00004000 4011 0000 0a000001 0a000002"));
+        file.extend(&3022u16.to_be_bytes());
+        file.extend(&50000u16.to_be_bytes());
+        file.extend(&(udp_length as u16).to_be_bytes());
+        file.extend(&hex!("0000"));
+        file.extend(payload);
+    }
+    file
+}
+
+// path in the temp dir, unique per process and call, the file is removed when dropped
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    pub fn new(extension: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "zwift_capture_{}_{}.{}",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::SeqCst),
+            extension
+        ));
+        TempFile { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// capture file in the temp dir, removed when dropped
+pub struct TempCapture {
+    file: TempFile,
+}
+
+impl TempCapture {
+    pub fn new(payload: &[u8], seconds: &[u32]) -> Self {
+        let file = TempFile::new("pcap");
+        std::fs::write(file.path(), capture_file(payload, seconds)).unwrap();
+        TempCapture { file }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+// PlayerState f19 and f20 bitfields, reverse engineered, as in community decoders
+//   f19: bit 1 - turning, bit 2 - forward on the road, bit 3 - steering,
+//        bits 16..24 - course
+//   f20: bits 0..4 - active powerup, bits 8..16 - road id, bits 24..32 - ride ons
+const F19_TURNING: i32 = 0x2;
+const F19_FORWARD: i32 = 0x4;
+const F19_STEERING: i32 = 0x8;
+const F19_COURSE_SHIFT: i32 = 16;
+const F20_POWER_UP: i32 = 0xf;
+const F20_ROAD_SHIFT: i32 = 8;
+const F20_RIDE_ONS_SHIFT: i32 = 24;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerUp {
+    None,
+    Feather,
+    Draft,
+    SmallXp,
+    LargeXp,
+    Burrito,
+    Aero,
+    Ghost,
+    Steamroller,
+    Anvil,
+    Unknown(i32),
+}
+
+impl PowerUp {
+    pub fn from(id: i32) -> Self {
+        match id {
+            0 => PowerUp::Feather,
+            1 => PowerUp::Draft,
+            2 => PowerUp::SmallXp,
+            3 => PowerUp::LargeXp,
+            4 => PowerUp::Burrito,
+            5 => PowerUp::Aero,
+            6 => PowerUp::Ghost,
+            7 => PowerUp::Steamroller,
+            8 => PowerUp::Anvil,
+            15 => PowerUp::None,
+            id => PowerUp::Unknown(id),
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        match self {
+            PowerUp::Feather => 0,
+            PowerUp::Draft => 1,
+            PowerUp::SmallXp => 2,
+            PowerUp::LargeXp => 3,
+            PowerUp::Burrito => 4,
+            PowerUp::Aero => 5,
+            PowerUp::Ghost => 6,
+            PowerUp::Steamroller => 7,
+            PowerUp::Anvil => 8,
+            PowerUp::None => 15,
+            PowerUp::Unknown(id) => *id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerFlags {
+    pub course: i32,
+    pub road_id: i32,
+    pub forward: bool, // false when riding the road in reverse
+    pub turning: bool,
+    pub steering: bool,
+    pub ride_ons: i32,
+    pub power_up: PowerUp,
+
+    // raw values for bits not decoded yet
+    pub f19: i32,
+    pub f20: i32,
+}
+
+impl PlayerFlags {
+    pub fn from(f19: i32, f20: i32) -> Self {
+        PlayerFlags {
+            course: (f19 >> F19_COURSE_SHIFT) & 0xff,
+            road_id: (f20 >> F20_ROAD_SHIFT) & 0xff,
+            forward: f19 & F19_FORWARD != 0,
+            turning: f19 & F19_TURNING != 0,
+            steering: f19 & F19_STEERING != 0,
+            ride_ons: (f20 >> F20_RIDE_ONS_SHIFT) & 0xff,
+            power_up: PowerUp::from(f20 & F20_POWER_UP),
+            f19,
+            f20,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::fixtures::FROM_SERVER;
+    use crate::flags::{PlayerFlags, PowerUp};
+    use crate::ZwiftMessage;
+
+    #[test]
+    fn decode_flags() {
+        let players = ZwiftMessage::FromServer(&FROM_SERVER)
+            .get_players()
+            .unwrap();
+        let raw: Vec<_> = players
+            .iter()
+            .map(|player| (player.flags.f19, player.flags.f20))
+            .collect();
+        assert_eq!(
+            raw,
+            vec![
+                (0x0006_0015, 0x0200_000f),
+                (0x0206_0010, 0x0100_000f),
+                (0x0006_0011, 0x0200_000f),
+            ]
+        );
+        let decoded: Vec<_> = players
+            .iter()
+            .map(|player| (player.flags.forward, player.flags.ride_ons))
+            .collect();
+        assert_eq!(decoded, vec![(true, 2), (false, 1), (false, 2)]);
+        for player in &players {
+            assert_eq!(player.flags.course, 6);
+            assert_eq!(player.flags.road_id, 0);
+            assert!(!player.flags.turning);
+            assert!(!player.flags.steering);
+            assert_eq!(player.flags.power_up, PowerUp::None);
+        }
+
+        let flags = PlayerFlags::from(0xe, 0x0301_2a05);
+        assert_eq!(flags.road_id, 0x2a);
+        assert!(flags.forward && flags.turning && flags.steering);
+        assert_eq!(flags.ride_ons, 3);
+        assert_eq!(flags.power_up, PowerUp::Aero);
+    }
+
+    #[test]
+    fn power_up_ids() {
+        for id in 0..16 {
+            assert_eq!(PowerUp::from(id).id(), id);
+        }
+        assert_eq!(PowerUp::from(11), PowerUp::Unknown(11));
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Player;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum World {
+    Watopia,
+    Richmond,
+    London,
+    NewYork,
+    Innsbruck,
+    Bologna,
+    Yorkshire,
+    CritCity,
+    MakuriIslands,
+    France,
+    Paris,
+}
+
+// game origin in wgs84, approximate
+struct WorldMeta {
+    world: World,
+    id: i32,
+    course: i32, // PlayerFlags::course
+    name: &'static str,
+    lat: f64,
+    lon: f64,
+}
+
+const WORLDS: [WorldMeta; 11] = [
+    WorldMeta {
+        world: World::Watopia,
+        id: 1,
+        course: 6,
+        name: "Watopia",
+        lat: -11.64490,
+        lon: 166.95293,
+    },
+    WorldMeta {
+        world: World::Richmond,
+        id: 2,
+        course: 2,
+        name: "Richmond",
+        lat: 37.5436,
+        lon: -77.4420,
+    },
+    WorldMeta {
+        world: World::London,
+        id: 3,
+        course: 7,
+        name: "London",
+        lat: 51.4981,
+        lon: -0.1166,
+    },
+    WorldMeta {
+        world: World::NewYork,
+        id: 4,
+        course: 8,
+        name: "New York",
+        lat: 40.7791,
+        lon: -73.9725,
+    },
+    WorldMeta {
+        world: World::Innsbruck,
+        id: 5,
+        course: 9,
+        name: "Innsbruck",
+        lat: 47.2501,
+        lon: 11.4161,
+    },
+    WorldMeta {
+        world: World::Bologna,
+        id: 6,
+        course: 10,
+        name: "Bologna",
+        lat: 44.4927,
+        lon: 11.3149,
+    },
+    WorldMeta {
+        world: World::Yorkshire,
+        id: 7,
+        course: 11,
+        name: "Yorkshire",
+        lat: 53.9873,
+        lon: -1.5671,
+    },
+    WorldMeta {
+        world: World::CritCity,
+        id: 8,
+        course: 12,
+        name: "Crit City",
+        lat: -10.3848,
+        lon: 165.8016,
+    },
+    WorldMeta {
+        world: World::MakuriIslands,
+        id: 9,
+        course: 13,
+        name: "Makuri Islands",
+        lat: -10.7842,
+        lon: 165.8367,
+    },
+    WorldMeta {
+        world: World::France,
+        id: 10,
+        course: 14,
+        name: "France",
+        lat: -21.6990,
+        lon: 166.1998,
+    },
+    WorldMeta {
+        world: World::Paris,
+        id: 11,
+        course: 15,
+        name: "Paris",
+        lat: 48.8676,
+        lon: 2.3142,
+    },
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LatLon {
+    pub lat: f64,
+    pub lon: f64,
+    pub elevation: f64, // m
+}
+
+// metres per degree of latitude and longitude at given latitude
+fn degree_distance(lat: f64) -> (f64, f64) {
+    let phi = lat.to_radians();
+    let lat_distance = 111_132.92 - 559.82 * (2. * phi).cos() + 1.175 * (4. * phi).cos();
+    let lon_distance = 111_412.84 * phi.cos() - 93.5 * (3. * phi).cos();
+    (lat_distance, lon_distance)
+}
+
+// zwift altitude is in cm, doubled and offset by 9000
+pub fn elevation(altitude: f32) -> f64 {
+    (altitude as f64 - 9000.) / 2. / 100.
+}
+
+impl World {
+    fn meta(&self) -> &'static WorldMeta {
+        WORLDS.iter().find(|meta| meta.world == *self).unwrap()
+    }
+
+    // WorldAttributes.world_id
+    pub fn from_id(id: i32) -> Option<World> {
+        WORLDS
+            .iter()
+            .find(|meta| meta.id == id)
+            .map(|meta| meta.world)
+    }
+
+    // course id from the f19 player flags
+    pub fn from_course(course: i32) -> Option<World> {
+        WORLDS
+            .iter()
+            .find(|meta| meta.course == course)
+            .map(|meta| meta.world)
+    }
+
+    pub fn id(&self) -> i32 {
+        self.meta().id
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.meta().name
+    }
+
+    // x, y in metres, x points north and y points east
+    pub fn to_lat_lon(&self, x: f64, y: f64, elevation: f64) -> LatLon {
+        let meta = self.meta();
+        let (lat_distance, lon_distance) = degree_distance(meta.lat);
+        LatLon {
+            lat: meta.lat + x / lat_distance,
+            lon: meta.lon + y / lon_distance,
+            elevation,
+        }
+    }
+
+    pub fn player_position(&self, player: &Player) -> LatLon {
+        self.to_lat_lon(player.x, player.y, player.altitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::geo::{elevation, World};
+
+    #[test]
+    fn world_ids() {
+        assert_eq!(World::from_id(1), Some(World::Watopia));
+        assert_eq!(World::from_id(11).unwrap().name(), "Paris");
+        assert_eq!(World::from_id(42), None);
+        assert_eq!(World::from_course(6), Some(World::Watopia));
+        assert_eq!(World::from_course(2), Some(World::Richmond));
+        assert_eq!(World::from_course(7), Some(World::London));
+        assert_eq!(World::from_course(1), None);
+    }
+
+    #[test]
+    fn convert_position() {
+        let origin = World::London.to_lat_lon(0., 0., 0.);
+        assert_eq!((origin.lat, origin.lon), (51.4981, -0.1166));
+        let moved = World::London.to_lat_lon(1_000., 1_000., elevation(29_000.));
+        assert!((moved.lat - origin.lat - 0.008990).abs() < 1e-5);
+        assert!((moved.lon - origin.lon - 0.014408).abs() < 1e-5);
+        assert_eq!(moved.elevation, 100.);
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::world::WorldState;
+use crate::{Event, Player};
+
+// riders less than this many seconds apart at their speed ride together, as in race timing
+pub const GROUP_GAP: f64 = 1.;
+// slow or stopped riders still group within this distance, m
+const GROUP_DISTANCE: f64 = 5.;
+// riders with headings further apart go different ways, degrees
+const SAME_DIRECTION: f64 = 45.;
+// wheel range behind the rider ahead that is drafting, m
+const DRAFT_BEHIND: (f64, f64) = (0.5, 4.);
+const DRAFT_LATERAL: f64 = 1.5;
+// groups are recomputed at most this often, millis of world time
+const UPDATE_INTERVAL: i64 = 1_000;
+// riders moving at least this far get their direction from positions, m
+const MIN_MOVEMENT: f64 = 1.;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Group {
+    pub id: u32,
+    pub riders: Vec<i32>, // front first
+    pub leader: i32,
+    pub speed: f64, // m per sec, mean
+    pub x: f64,     // leader position, m
+    pub y: f64,
+    // next group ahead in the same direction and seconds to reach its last rider
+    pub ahead: Option<u32>,
+    pub gap_ahead: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Draft {
+    pub rider_id: i32,
+    pub wheel_id: i32, // rider ahead
+    pub distance: f64, // m behind
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum GroupEvent {
+    Dropped {
+        rider_id: i32,
+        group_id: u32,
+        world_time: i64,
+    },
+    Bridged {
+        rider_id: i32,
+        group_id: u32,
+        world_time: i64,
+    },
+}
+
+// last position the direction was taken from and the direction as unit vector
+struct Motion {
+    x: f64,
+    y: f64,
+    direction: (f64, f64),
+}
+
+struct Rider {
+    id: i32,
+    x: f64,
+    y: f64,
+    speed: f64,
+    direction: (f64, f64),
+}
+
+impl Rider {
+    // along and lateral offset of other rider in this rider's direction
+    fn offset(&self, other: &Rider) -> (f64, f64) {
+        let (dx, dy) = (other.x - self.x, other.y - self.y);
+        let (ux, uy) = self.direction;
+        (dx * ux + dy * uy, (ux * dy - uy * dx).abs())
+    }
+
+    fn distance(&self, other: &Rider) -> f64 {
+        (other.x - self.x).hypot(other.y - self.y)
+    }
+
+    fn same_direction(&self, other: &Rider) -> bool {
+        same_direction(self.direction, other.direction)
+    }
+}
+
+fn same_direction(a: (f64, f64), b: (f64, f64)) -> bool {
+    a.0 * b.0 + a.1 * b.1 >= SAME_DIRECTION.to_radians().cos()
+}
+
+fn normalize((x, y): (f64, f64)) -> (f64, f64) {
+    let length = x.hypot(y);
+    if length > 0. {
+        (x / length, y / length)
+    } else {
+        (1., 0.)
+    }
+}
+
+// heading axis is a guess, positions are preferred once riders move
+fn heading_direction(player: &Player) -> (f64, f64) {
+    let heading = player.heading as f64 / 1_000_000.;
+    (heading.cos(), heading.sin())
+}
+
+// protobuf floats may be nan or infinite off the wire
+fn finite(player: &Player) -> bool {
+    player.x.is_finite() && player.y.is_finite() && player.speed.is_finite()
+}
+
+fn find(parent: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    parent[index] = root;
+    root
+}
+
+// clusters riders into groups on the road from positions, headings and speeds
+//   distances are straight lines, gaps on winding roads are approximate
+#[derive(Default)]
+pub struct GroupTracker {
+    world: WorldState,
+    motion: HashMap<i32, Motion>,
+    groups: Vec<Group>,
+    drafts: Vec<Draft>,
+    membership: HashMap<i32, u32>,
+    next_id: u32,
+    updated: i64, // world time
+}
+
+impl GroupTracker {
+    pub fn new() -> Self {
+        GroupTracker::default()
+    }
+
+    // returns drop and bridge events when groups were recomputed
+    pub fn ingest(&mut self, event: &Event) -> Vec<GroupEvent> {
+        self.world.ingest(event);
+        for player in &event.players {
+            self.update_motion(player);
+        }
+        if self.world.world_time() - self.updated < UPDATE_INTERVAL {
+            return vec![];
+        }
+        self.update()
+    }
+
+    fn update_motion(&mut self, player: &Player) {
+        if !finite(player) {
+            return;
+        }
+        let motion = self.motion.entry(player.id).or_insert_with(|| Motion {
+            x: player.x,
+            y: player.y,
+            direction: heading_direction(player),
+        });
+        let (dx, dy) = (player.x - motion.x, player.y - motion.y);
+        if dx.hypot(dy) >= MIN_MOVEMENT {
+            motion.direction = normalize((dx, dy));
+            motion.x = player.x;
+            motion.y = player.y;
+        }
+    }
+
+    pub fn update(&mut self) -> Vec<GroupEvent> {
+        self.updated = self.world.world_time();
+        let world = &self.world;
+        self.motion.retain(|id, _| world.get(*id).is_some());
+
+        let riders: Vec<Rider> = self
+            .world
+            .riders()
+            .map(|state| &state.player)
+            .filter(|player| !player.just_watching && finite(player))
+            .filter_map(|player| {
+                self.motion.get(&player.id).map(|motion| Rider {
+                    id: player.id,
+                    x: player.x,
+                    y: player.y,
+                    speed: player.speed,
+                    direction: motion.direction,
+                })
+            })
+            .collect();
+
+        let mut parent: Vec<usize> = (0..riders.len()).collect();
+        for (i, a) in riders.iter().enumerate() {
+            for (j, b) in riders.iter().enumerate().skip(i + 1) {
+                let reach = GROUP_DISTANCE.max((a.speed + b.speed) / 2. * GROUP_GAP);
+                if a.same_direction(b) && a.distance(b) <= reach {
+                    let (i, j) = (find(&mut parent, i), find(&mut parent, j));
+                    parent[i] = j;
+                }
+            }
+        }
+        let mut components: HashMap<usize, Vec<usize>> = HashMap::new();
+        for index in 0..riders.len() {
+            let root = find(&mut parent, index);
+            components.entry(root).or_default().push(index);
+        }
+        let mut components: Vec<Vec<usize>> = components.into_values().collect();
+        // larger groups keep their id when groups merge or split
+        components.sort_by_key(|component| std::cmp::Reverse(component.len()));
+
+        let mut groups = vec![];
+        let mut drafts = vec![];
+        let mut membership = HashMap::new();
+        for mut component in components {
+            let direction = normalize(component.iter().fold((0., 0.), |sum, &index| {
+                (
+                    sum.0 + riders[index].direction.0,
+                    sum.1 + riders[index].direction.1,
+                )
+            }));
+            let along = |rider: &Rider| rider.x * direction.0 + rider.y * direction.1;
+            component.sort_by(|&a, &b| along(&riders[b]).total_cmp(&along(&riders[a])));
+
+            let mut votes: HashMap<u32, usize> = HashMap::new();
+            for &index in &component {
+                if let Some(id) = self.membership.get(&riders[index].id) {
+                    *votes.entry(*id).or_insert(0) += 1;
+                }
+            }
+            let claimed = |id: &u32| groups.iter().any(|group: &Group| group.id == *id);
+            let id = match votes
+                .into_iter()
+                .filter(|(id, _)| !claimed(id))
+                .max_by_key(|&(id, count)| (count, std::cmp::Reverse(id)))
+            {
+                Some((id, _)) => id,
+                None => {
+                    self.next_id += 1;
+                    self.next_id
+                }
+            };
+
+            for (position, &index) in component.iter().enumerate() {
+                let rider = &riders[index];
+                membership.insert(rider.id, id);
+                let wheel = component[..position]
+                    .iter()
+                    .map(|&ahead| (&riders[ahead], rider.offset(&riders[ahead])))
+                    .filter(|(_, (along, lateral))| {
+                        *along >= DRAFT_BEHIND.0
+                            && *along <= DRAFT_BEHIND.1
+                            && *lateral <= DRAFT_LATERAL
+                    })
+                    .min_by(|(_, a), (_, b)| a.0.total_cmp(&b.0));
+                if let Some((ahead, (distance, _))) = wheel {
+                    drafts.push(Draft {
+                        rider_id: rider.id,
+                        wheel_id: ahead.id,
+                        distance,
+                    });
+                }
+            }
+
+            let leader = &riders[component[0]];
+            let speed = component
+                .iter()
+                .map(|&index| riders[index].speed)
+                .sum::<f64>()
+                / component.len() as f64;
+            groups.push(Group {
+                id,
+                riders: component.iter().map(|&index| riders[index].id).collect(),
+                leader: leader.id,
+                speed,
+                x: leader.x,
+                y: leader.y,
+                ahead: None,
+                gap_ahead: None,
+            });
+        }
+
+        // chasing group front to the last rider of the nearest group ahead
+        let positions: HashMap<i32, &Rider> =
+            riders.iter().map(|rider| (rider.id, rider)).collect();
+        let tails: Vec<(u32, &Rider, (f64, f64))> = groups
+            .iter()
+            .map(|group| {
+                let tail = positions[group.riders.last().unwrap()];
+                (group.id, tail, positions[&group.leader].direction)
+            })
+            .collect();
+        for group in groups.iter_mut() {
+            let front = positions[&group.leader];
+            let nearest = tails
+                .iter()
+                .filter(|(_, _, direction)| same_direction(front.direction, *direction))
+                .filter(|(_, tail, _)| {
+                    // within a cone ahead, excludes the front rider's own group
+                    let (along, lateral) = front.offset(tail);
+                    along > 0. && lateral <= along
+                })
+                .map(|(id, tail, _)| (*id, front.distance(tail)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((id, distance)) = nearest {
+                group.ahead = Some(id);
+                if group.speed > 0. {
+                    group.gap_ahead = Some(distance / group.speed);
+                }
+            }
+        }
+        let ids: Vec<u32> = groups.iter().map(|group| group.id).collect();
+
+        let world_time = self.world.world_time();
+        let mut events = vec![];
+        for (rider_id, group_id) in &membership {
+            let previous = match self.membership.get(rider_id) {
+                Some(previous) if previous != group_id => *previous,
+                _ => continue,
+            };
+            if ids.contains(&previous) {
+                events.push(GroupEvent::Dropped {
+                    rider_id: *rider_id,
+                    group_id: previous,
+                    world_time,
+                });
+            }
+            if self.membership.values().any(|id| id == group_id) {
+                events.push(GroupEvent::Bridged {
+                    rider_id: *rider_id,
+                    group_id: *group_id,
+                    world_time,
+                });
+            }
+        }
+
+        self.groups = groups;
+        self.drafts = drafts;
+        self.membership = membership;
+        events
+    }
+
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    pub fn drafts(&self) -> &[Draft] {
+        &self.drafts
+    }
+
+    // world time groups were last recomputed
+    pub fn updated(&self) -> i64 {
+        self.updated
+    }
+
+    pub fn group_of(&self, rider_id: i32) -> Option<&Group> {
+        let id = self.membership.get(&rider_id)?;
+        self.groups.iter().find(|group| group.id == *id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::groups::{GroupEvent, GroupTracker};
+    use crate::zwift_messages::{PlayerState, ServerToClient};
+    use crate::Event;
+
+    // riders at 10 m per sec, heading along x unless reversed
+    fn event(world_time: i64, riders: &[(i32, f32, f32, bool)]) -> Event {
+        let mut message = ServerToClient::new();
+        for &(id, x, y, reversed) in riders {
+            let mut state = PlayerState::new();
+            state.set_id(id);
+            state.set_worldTime(world_time);
+            state.set_x(x * 100.);
+            state.set_y(y * 100.);
+            state.set_speed(36_000_000);
+            if reversed {
+                state.set_heading(3_141_593);
+            }
+            message.mut_player_states().push(state);
+        }
+        Event::from_server(message)
+    }
+
+    #[test]
+    fn groups_drafts_and_gaps() {
+        let mut tracker = GroupTracker::new();
+        tracker.ingest(&event(
+            1_000,
+            &[
+                (1, 300., 0., false),
+                (2, 298., 0., false),
+                (3, 290., 0., false),
+                (4, 60., 0., false),
+                (5, 57., 1., false),
+                (6, 200., 0., true),
+                (7, f32::NAN, 0., false),
+            ],
+        ));
+        assert_eq!(tracker.groups().len(), 3);
+        assert!(tracker.group_of(7).is_none());
+        let breakaway = tracker.group_of(1).unwrap();
+        assert_eq!(breakaway.riders, vec![1, 2, 3]);
+        assert_eq!(breakaway.gap_ahead, None);
+
+        let chasers = tracker.group_of(4).unwrap();
+        assert_eq!(chasers.riders, vec![4, 5]);
+        assert_eq!(chasers.ahead, Some(breakaway.id));
+        assert!((chasers.gap_ahead.unwrap() - 23.).abs() < 1e-6);
+        assert_eq!(tracker.group_of(6).unwrap().riders, vec![6]);
+
+        let mut drafts: Vec<_> = tracker
+            .drafts()
+            .iter()
+            .map(|draft| (draft.rider_id, draft.wheel_id))
+            .collect();
+        drafts.sort();
+        assert_eq!(drafts, vec![(2, 1), (5, 4)]);
+    }
+
+    #[test]
+    fn drop_and_bridge() {
+        let mut tracker = GroupTracker::new();
+        tracker.ingest(&event(
+            1_000,
+            &[
+                (1, 0., 0., false),
+                (2, -3., 0., false),
+                (3, -6., 0., false),
+                (4, -60., 0., false),
+            ],
+        ));
+        let group_id = tracker.group_of(1).unwrap().id;
+        // no regrouping within the update interval
+        assert!(tracker
+            .ingest(&event(1_500, &[(4, -3., 0., false)]))
+            .is_empty());
+
+        let mut events = tracker.ingest(&event(
+            2_000,
+            &[
+                (1, 12., 0., false),
+                (2, 9., 0., false),
+                (3, -6., 0., false),
+                (4, 6., 0., false),
+            ],
+        ));
+        events.sort_by_key(|event| match event {
+            GroupEvent::Dropped { rider_id, .. } | GroupEvent::Bridged { rider_id, .. } => {
+                *rider_id
+            }
+        });
+        assert_eq!(
+            events,
+            vec![
+                GroupEvent::Dropped {
+                    rider_id: 3,
+                    group_id,
+                    world_time: 2_000
+                },
+                GroupEvent::Bridged {
+                    rider_id: 4,
+                    group_id,
+                    world_time: 2_000
+                },
+            ]
+        );
+        assert_eq!(tracker.group_of(4).unwrap().riders, vec![1, 2, 4]);
+    }
+}
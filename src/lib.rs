@@ -1,13 +1,55 @@
+pub mod builder;
+pub mod clock;
+pub mod datagram;
+pub mod direction;
+pub mod error;
+pub mod events;
+pub mod fit;
+#[cfg(test)]
+mod fixtures;
+pub mod flags;
+pub mod geo;
+pub mod groups;
+mod http;
+pub mod link;
+pub mod metrics;
+pub mod multi;
+pub mod power;
+pub mod quality;
+pub mod race;
+pub mod recorder;
+pub mod roster;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod session;
+#[cfg(feature = "tokio")]
+pub mod stream;
+pub mod tcp;
+pub mod world;
 pub mod zwift_messages;
 
-use etherparse::{SlicedPacket, TransportSlice};
-use pcap::{Activated, Active, Capture, Device, Offline};
+use etherparse::{InternetSlice, TransportSlice};
+use pcap::{Activated, Active, Capture, Device, Offline, Stat};
 use protobuf::Message;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
-
+use std::sync::atomic::{self, AtomicBool};
+
+use crate::datagram::ClientDatagramHeader;
+use crate::direction::ServerPorts;
+use crate::error::{Result, ZwiftCaptureError};
+use crate::events::GameEvent;
+use crate::flags::PlayerFlags;
+use crate::session::SessionInfo;
+use crate::tcp::{StreamKey, TcpReassembler, TcpSegment};
 use crate::zwift_messages::{ClientToServer, ServerToClient};
 
+pub const UDP_PORT: u16 = 3022;
+pub const TCP_PORT: u16 = 3023;
+const CAPTURE_FILTER: &str = "udp port 3022 or tcp port 3023";
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Player {
     pub id: i32,
@@ -17,16 +59,21 @@ pub struct Player {
     // in game position coordinates, m
     pub x: f64,
     pub y: f64,
+    pub altitude: f64, // m, approx
 
-    pub heading: i64,
+    pub heading: i64,         // microradians
+    pub heading_degrees: f64, // 0 - 360
     pub lean: i32,
 
     pub road_position: i32, // ???
+    pub road_time: i32,     // position along the road, ???
 
     pub speed: f64, // m per sec
 
-    pub distance: i32, // m
-    pub time: i32,     // sec
+    pub distance: i32,      // m
+    pub true_distance: f64, // m, including lateral movement
+    pub time: i32,          // sec
+    pub progress: i32,      // ???
 
     pub laps: i32,
     pub climbing: i32, // m
@@ -35,9 +82,15 @@ pub struct Player {
     pub heartrate: i32, // bpm
     pub power: i32,     // watts
 
-    // 15 - None, 0 - feather, 1 - draft, 5 - aero
-    pub power_up: i32,
+    pub calories: i32, // raw, mechanical work in calories
+    pub work_kj: f64,  // mechanical work, about the kcal shown in game
+
+    // course, road id, direction, powerup etc from f19 and f20
+    pub flags: PlayerFlags,
     pub watching_rider_id: i32,
+    pub just_watching: bool,
+    pub customisation_id: i64,
+    pub sport: Sport,
 }
 
 impl Player {
@@ -50,13 +103,21 @@ impl Player {
             // in game position coordinates, cm to m?
             x: player_state.get_x() as f64 / 100.,
             y: player_state.get_y() as f64 / 100.,
+            altitude: geo::elevation(player_state.get_altitude()),
 
             heading: player_state.get_heading(),
+            heading_degrees: (player_state.get_heading() as f64 / 1_000_000.)
+                .to_degrees()
+                .rem_euclid(360.),
             lean: player_state.get_lean(),
 
             road_position: player_state.get_roadPosition(),
+            road_time: player_state.get_roadTime(),
             distance: player_state.get_distance(),
+            // cm like x and y
+            true_distance: player_state.get_f34() as f64 / 100.,
             time: player_state.get_time(),
+            progress: player_state.get_progress(),
 
             laps: player_state.get_laps(),
             // orginal mm per hour?
@@ -69,8 +130,100 @@ impl Player {
             heartrate: player_state.get_heartrate(),
             power: player_state.get_power(),
 
-            power_up: player_state.get_f20() & 0xf,
+            calories: player_state.get_calories(),
+            work_kj: player_state.get_calories() as f64 * 4.184 / 1000.,
+
+            flags: PlayerFlags::from(player_state.get_f19(), player_state.get_f20()),
             watching_rider_id: player_state.get_watchingRiderId(),
+            just_watching: player_state.get_justWatching() != 0,
+            customisation_id: player_state.get_customisationId(),
+            sport: Sport::from(player_state.get_sport()),
+        }
+    }
+
+    // seconds per km, runners only
+    pub fn run_pace(&self) -> Option<f64> {
+        match self.sport {
+            Sport::Running if self.speed > 0. => Some(1000. / self.speed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Sport {
+    Cycling,
+    Running,
+    Unknown(i64),
+}
+
+impl Sport {
+    pub fn from(sport: i64) -> Self {
+        match sport {
+            0 => Sport::Cycling,
+            1 => Sport::Running,
+            sport => Sport::Unknown(sport),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    FromServer,
+    ToServer,
+}
+
+#[derive(Debug, Clone)]
+pub enum DecodedMessage {
+    FromServer(ServerToClient),
+    ToServer(ClientDatagramHeader, ClientToServer),
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub message: DecodedMessage,
+    pub players: Vec<Player>,
+    pub game_events: Vec<GameEvent>,
+    pub capture_time: i64, // packet timestamp, micros since unix epoch, 0 if unknown
+    pub interface: Option<String>, // capture device, None for files
+}
+
+impl Event {
+    pub fn from_server(message: ServerToClient) -> Self {
+        let players = message
+            .player_states
+            .iter()
+            .map(|data| Player::from(data))
+            .collect();
+        let game_events = GameEvent::from_message(&message);
+        Event {
+            message: DecodedMessage::FromServer(message),
+            players,
+            game_events,
+            capture_time: 0,
+            interface: None,
+        }
+    }
+
+    pub fn to_server(header: ClientDatagramHeader, message: ClientToServer) -> Self {
+        let players = if message.has_state() {
+            vec![Player::from(message.get_state())]
+        } else {
+            vec![]
+        };
+        Event {
+            message: DecodedMessage::ToServer(header, message),
+            players,
+            game_events: vec![],
+            capture_time: 0,
+            interface: None,
+        }
+    }
+
+    pub fn direction(&self) -> Direction {
+        match self.message {
+            DecodedMessage::FromServer(_) => Direction::FromServer,
+            DecodedMessage::ToServer(_, _) => Direction::ToServer,
         }
     }
 }
@@ -78,6 +231,8 @@ impl Player {
 pub enum ZwiftMessage<'a> {
     FromServer(&'a [u8]),
     ToServer(&'a [u8]),
+    // frames completed by a tcp segment, may be empty
+    FromServerTcp(Vec<ServerToClient>),
     InvalidMessage(&'a [u8]),
 }
 
@@ -97,64 +252,317 @@ impl<'a> ZwiftMessage<'a> {
                     Some(vec![])
                 }
             }
-            ZwiftMessage::ToServer(payload) => {
-                // looks like protobuf message starts after X bytes with 0x8 as first byte
-                // first byte seems to be used as offset index
-                let mut offset = (payload[0] - 1) as usize;
-                let limit = (payload.len() - 4) as usize;
-                if offset >= limit {
-                    for (ix, &byte) in payload.iter().enumerate() {
-                        if byte == 0x8 as u8 {
-                            offset = ix;
-                            break;
-                        } else if ix == limit {
-                            offset = 0;
-                            break;
-                        }
-                    }
-                }
-                if let Ok(message) = ClientToServer::parse_from_bytes(&payload[offset..limit]) {
+            ZwiftMessage::ToServer(_) => {
+                if let Ok((_, message)) = self.parse_to_server() {
                     Some(vec![Player::from(message.get_state())])
                 } else {
                     Some(vec![])
                 }
             }
-            ZwiftMessage::InvalidMessage(payload) => Some(vec![]),
+            ZwiftMessage::FromServerTcp(messages) => Some(
+                messages
+                    .iter()
+                    .flat_map(|message| message.player_states.iter())
+                    .map(|data| Player::from(data))
+                    .collect(),
+            ),
+            ZwiftMessage::InvalidMessage(_) => Some(vec![]),
         };
     }
+
+    pub fn parse_to_server(&self) -> Result<(ClientDatagramHeader, ClientToServer)> {
+        match self {
+            ZwiftMessage::ToServer(payload) => {
+                let (header, body) = ClientDatagramHeader::parse(payload)?;
+                Ok((header, ClientToServer::parse_from_bytes(body)?))
+            }
+            _ => Err(ZwiftCaptureError::UnexpectedMessage),
+        }
+    }
+
+    // unlike get_players reports corrupt packets as errors
+    pub fn decode(&self) -> Result<Vec<Event>> {
+        match self {
+            ZwiftMessage::FromServer(payload) => Ok(vec![Event::from_server(
+                ServerToClient::parse_from_bytes(payload)?,
+            )]),
+            ZwiftMessage::ToServer(_) => {
+                let (header, message) = self.parse_to_server()?;
+                Ok(vec![Event::to_server(header, message)])
+            }
+            ZwiftMessage::FromServerTcp(messages) => Ok(messages
+                .iter()
+                .cloned()
+                .map(|message| Event::from_server(message))
+                .collect()),
+            // unrelated traffic
+            ZwiftMessage::InvalidMessage(_) => Ok(vec![]),
+        }
+    }
+
+    pub fn get_events(&self) -> Option<Vec<GameEvent>> {
+        self.get_players_and_events().map(|(_, events)| events)
+    }
+
+    pub fn get_players_and_events(&self) -> Option<(Vec<Player>, Vec<GameEvent>)> {
+        let from_messages = |messages: &[ServerToClient]| {
+            let players = messages
+                .iter()
+                .flat_map(|message| message.player_states.iter())
+                .map(|data| Player::from(data))
+                .collect();
+            let events = messages
+                .iter()
+                .flat_map(|message| GameEvent::from_message(message))
+                .collect();
+            (players, events)
+        };
+        match self {
+            ZwiftMessage::FromServer(payload) => {
+                if let Ok(message) = ServerToClient::parse_from_bytes(payload) {
+                    Some(from_messages(&[message]))
+                } else {
+                    Some((vec![], vec![]))
+                }
+            }
+            ZwiftMessage::FromServerTcp(messages) => Some(from_messages(messages)),
+            _ => self.get_players().map(|players| (players, vec![])),
+        }
+    }
 }
 
 pub struct ZwiftCapture<T> {
     capture: T,
+    linktype: i32,
+    tcp: TcpReassembler,
+    // decoded events and errors not yet returned by next_event
+    pending: VecDeque<Result<Event>>,
+    // corrupt tcp frames of the latest packet, reported after its events
+    frame_errors: Vec<ZwiftCaptureError>,
+    // timestamp of the latest packet, micros since unix epoch
+    timestamp: i64,
+    // source and destination of the latest packet
+    addresses: Option<(IpAddr, IpAddr)>,
+    session: SessionInfo,
+    ports: ServerPorts,
+    // extra bpf expressions narrowing the ports filter, all must match
+    filters: Vec<String>,
+    // device name events are tagged with
+    interface: Option<String>,
+}
+
+// decoded frames and errors of the corrupt ones
+fn parse_frames(frames: Vec<Vec<u8>>) -> (Vec<ServerToClient>, Vec<ZwiftCaptureError>) {
+    let mut messages = vec![];
+    let mut errors = vec![];
+    for frame in frames {
+        match ServerToClient::parse_from_bytes(&frame) {
+            Ok(message) => messages.push(message),
+            Err(error) => errors.push(error.into()),
+        }
+    }
+    (messages, errors)
+}
+
+fn ip_addresses(ip: &Option<InternetSlice>) -> Option<(IpAddr, IpAddr)> {
+    match ip {
+        Some(InternetSlice::Ipv4(header)) => Some((
+            IpAddr::V4(header.source_addr()),
+            IpAddr::V4(header.destination_addr()),
+        )),
+        Some(InternetSlice::Ipv6(header, _)) => Some((
+            IpAddr::V6(header.source_addr()),
+            IpAddr::V6(header.destination_addr()),
+        )),
+        None => None,
+    }
 }
 
 impl<T: Activated> ZwiftCapture<Capture<T>> {
+    fn with_capture(capture: Capture<T>) -> Self {
+        ZwiftCapture {
+            linktype: capture.get_datalink().0,
+            capture,
+            tcp: TcpReassembler::new(),
+            pending: VecDeque::new(),
+            frame_errors: vec![],
+            timestamp: 0,
+            addresses: None,
+            session: SessionInfo::new(),
+            ports: ServerPorts::default(),
+            filters: vec![],
+            interface: None,
+        }
+    }
+
+    pub fn try_next_payload(&mut self) -> Result<ZwiftMessage> {
+        self.frame_errors.clear();
+        let packet = self.capture.next()?;
+        let ts = packet.header.ts;
+        self.timestamp = ts.tv_sec as i64 * 1_000_000 + ts.tv_usec as i64;
+        let parsed = link::slice_packet(self.linktype, packet.data)?;
+        self.addresses = ip_addresses(&parsed.ip);
+        let message = match parsed.transport {
+            Some(TransportSlice::Udp(u)) => {
+                let direction = match self.addresses {
+                    Some((source, destination)) => direction::classify(
+                        &self.session,
+                        &self.ports,
+                        SocketAddr::new(source, u.source_port()),
+                        SocketAddr::new(destination, u.destination_port()),
+                        parsed.payload,
+                    ),
+                    None if self.ports.is_udp(u.source_port()) => Direction::FromServer,
+                    None => Direction::ToServer,
+                };
+                match direction {
+                    Direction::FromServer => ZwiftMessage::FromServer(parsed.payload),
+                    Direction::ToServer => ZwiftMessage::ToServer(parsed.payload),
+                }
+            }
+            Some(TransportSlice::Tcp(t)) if self.ports.is_tcp(t.source_port()) => {
+                match self.addresses {
+                    Some((source, destination)) => {
+                        let key = StreamKey {
+                            source: SocketAddr::new(source, t.source_port()),
+                            destination: SocketAddr::new(destination, t.destination_port()),
+                        };
+                        let segment = TcpSegment {
+                            sequence: t.sequence_number(),
+                            syn: t.syn(),
+                            fin: t.fin(),
+                            rst: t.rst(),
+                            payload: parsed.payload,
+                        };
+                        // frames are already consumed, a corrupt one must not lose the others
+                        let (messages, errors) = parse_frames(self.tcp.push(key, &segment));
+                        self.frame_errors = errors;
+                        ZwiftMessage::FromServerTcp(messages)
+                    }
+                    None => ZwiftMessage::InvalidMessage(parsed.payload),
+                }
+            }
+            _ => ZwiftMessage::InvalidMessage(parsed.payload),
+        };
+        Ok(message)
+    }
+
+    // packets that fail to parse and read timeouts come back as InvalidMessage,
+    // None when the capture ends or fails
     pub fn next_payload(&mut self) -> Option<ZwiftMessage> {
-        match self.capture.next() {
-            Ok(packet) => {
-                return match SlicedPacket::from_ethernet(packet.data) {
-                    Ok(parsed) => match parsed.transport {
-                        Some(TransportSlice::Udp(u)) => {
-                            let source_port = u.source_port();
-                            if source_port == 3022 {
-                                Some(ZwiftMessage::FromServer(parsed.payload))
-                            } else {
-                                Some(ZwiftMessage::ToServer(parsed.payload))
-                            }
+        match self.try_next_payload() {
+            Ok(message) => Some(message),
+            Err(ZwiftCaptureError::Pcap(pcap::Error::TimeoutExpired)) => {
+                Some(ZwiftMessage::InvalidMessage(&[]))
+            }
+            Err(ZwiftCaptureError::Pcap(_)) => None,
+            Err(_) => Some(ZwiftMessage::InvalidMessage(&[])),
+        }
+    }
+
+    // returns None at the end of capture file
+    pub fn next_event(&mut self) -> Option<Result<Event>> {
+        loop {
+            match self.poll_event() {
+                Some(Err(ZwiftCaptureError::Pcap(pcap::Error::TimeoutExpired))) => continue,
+                event => return event,
+            }
+        }
+    }
+
+    // like next_event but returns read timeouts, callers get a chance to stop
+    pub fn poll_event(&mut self) -> Option<Result<Event>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            let events = match self.try_next_payload() {
+                Ok(message) => message.decode(),
+                Err(ZwiftCaptureError::Pcap(pcap::Error::NoMorePackets)) => return None,
+                Err(error) => Err(error),
+            };
+            match events {
+                Ok(events) => {
+                    let known = self.session.servers.len();
+                    for event in &events {
+                        self.session.ingest(event);
+                    }
+                    let capture_time = self.timestamp;
+                    let interface = &self.interface;
+                    self.pending.extend(events.into_iter().map(|mut event| {
+                        event.capture_time = capture_time;
+                        event.interface = interface.clone();
+                        Ok(event)
+                    }));
+                    self.pending.extend(self.frame_errors.drain(..).map(Err));
+                    // announced servers may use other ports
+                    if self.session.servers.len() != known {
+                        if let Err(error) = self.apply_filter() {
+                            self.pending.push_back(Err(error));
                         }
-                        _ => Some(ZwiftMessage::InvalidMessage(parsed.payload)),
-                    },
-                    _ => {
-                        println!("failed to parse");
-                        Some(ZwiftMessage::InvalidMessage(&[]))
                     }
                 }
+                Err(error) => return Some(Err(error)),
             }
-            Err(error) => {
-                println!("Error: {:?}", error);
+        }
+    }
+
+    // hands events to send until the capture ends or fails, send returns false or stop is set,
+    // live captures need a read timeout to notice stop while idle
+    pub fn forward<F>(&mut self, stop: &AtomicBool, mut send: F)
+    where
+        F: FnMut(Result<Event>) -> bool,
+    {
+        while !stop.load(atomic::Ordering::Relaxed) {
+            match self.poll_event() {
+                Some(Err(ZwiftCaptureError::Pcap(pcap::Error::TimeoutExpired))) => {}
+                // device went away, report once
+                Some(Err(ZwiftCaptureError::Pcap(error))) => {
+                    send(Err(error.into()));
+                    break;
+                }
+                Some(event) => {
+                    if !send(event) {
+                        break;
+                    }
+                }
+                None => break,
             }
         }
-        None
+    }
+
+    pub fn events(&mut self) -> Events<'_, T> {
+        Events { capture: self }
+    }
+
+    // narrows the capture filter with an extra bpf expression, earlier ones still apply
+    pub fn add_filter(&mut self, program: &str) -> Result<()> {
+        self.filters.push(program.to_string());
+        self.apply_filter()
+    }
+
+    // servers listening on other ports, the capture filter follows
+    pub fn set_server_ports(&mut self, ports: ServerPorts) -> Result<()> {
+        self.ports = ports;
+        self.apply_filter()
+    }
+
+    pub fn server_ports(&self) -> &ServerPorts {
+        &self.ports
+    }
+
+    fn apply_filter(&mut self) -> Result<()> {
+        let program = self.ports.filter_for(&self.session.servers, &self.filters);
+        Ok(self.capture.filter(&program, true)?)
+    }
+
+    // rider id, local ip and servers announced so far
+    pub fn session(&self) -> &SessionInfo {
+        &self.session
+    }
+
+    pub fn stats(&mut self) -> Result<Stat> {
+        Ok(self.capture.stats()?)
     }
 
     pub fn print_stat(&mut self) {
@@ -172,44 +580,74 @@ impl<T: Activated> ZwiftCapture<Capture<T>> {
 impl<T: Activated> Iterator for ZwiftCapture<Capture<T>> {
     type Item = Vec<Player>;
 
+    // empty for packets that fail to parse, ends with the capture
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(zwift_message) = self.next_payload() {
-            if let Some(players) = zwift_message.get_players() {
-                return Some(players);
+        loop {
+            match self.try_next_payload() {
+                Ok(message) => return message.get_players(),
+                Err(ZwiftCaptureError::Pcap(pcap::Error::TimeoutExpired)) => continue,
+                Err(ZwiftCaptureError::Pcap(_)) => return None,
+                Err(_) => return Some(vec![]),
             }
         }
-        None
+    }
+}
+
+pub struct Events<'a, T: Activated> {
+    capture: &'a mut ZwiftCapture<Capture<T>>,
+}
+
+impl<'a, T: Activated> Iterator for Events<'a, T> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.capture.next_event()
     }
 }
 
 impl ZwiftCapture<Capture<Active>> {
     pub fn new() -> Self {
-        let main_device = Device::lookup().unwrap();
-        let mut capture = main_device.open().unwrap();
-        let _ = capture.filter("udp port 3022", true).unwrap();
-        ZwiftCapture { capture }
+        ZwiftCapture::try_new().unwrap()
+    }
+
+    pub fn try_new() -> Result<Self> {
+        ZwiftCapture::try_from_device(Device::lookup()?)
     }
 
     pub fn from_device(device: Device) -> Self {
-        let mut capture = device.open().unwrap();
-        let _ = capture.filter("udp port 3022", true).unwrap();
-        ZwiftCapture { capture }
+        ZwiftCapture::try_from_device(device).unwrap()
+    }
+
+    pub fn try_from_device(device: Device) -> Result<Self> {
+        let interface = device.name.clone();
+        let mut capture = device.open()?;
+        capture.filter(CAPTURE_FILTER, true)?;
+        let mut capture = ZwiftCapture::with_capture(capture);
+        capture.interface = Some(interface);
+        Ok(capture)
     }
 }
 
 impl ZwiftCapture<Capture<Offline>> {
     pub fn from_file(path: &Path) -> Self {
-        let mut capture = Capture::from_file(path).unwrap();
-        let _ = capture.filter("udp port 3022", false).unwrap();
-        ZwiftCapture { capture }
+        ZwiftCapture::try_from_file(path).unwrap()
+    }
+
+    pub fn try_from_file(path: &Path) -> Result<Self> {
+        let mut capture = Capture::from_file(path)?;
+        capture.filter(CAPTURE_FILTER, false)?;
+        Ok(ZwiftCapture::with_capture(capture))
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::ZwiftMessage;
+    use crate::fixtures::FROM_SERVER;
+    use crate::zwift_messages::ServerToClient;
+    use crate::{parse_frames, Direction, Sport, ZwiftMessage};
     use hex_literal::hex;
+    use protobuf::Message;
 
     #[test]
     fn it_works_parse_from_server() {
@@ -224,6 +662,54 @@ mod tests {
         let message = ZwiftMessage::ToServer(&packet_payload);
         let players = message.get_players().unwrap();
         assert_eq!(players.len(), 1);
+        let events = message.decode().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].direction(), Direction::ToServer);
+    }
+
+    #[test]
+    fn decode_errors() {
+        let message = ZwiftMessage::ToServer(&[]);
+        assert!(message.get_players().unwrap().is_empty());
+        assert!(message.decode().is_err());
+        let message = ZwiftMessage::FromServer(&hex!("0a"));
+        assert!(message.decode().is_err());
+    }
+
+    #[test]
+    fn it_works_parse_from_server_tcp() {
+        let packet_payload = FROM_SERVER;
+        let message = ServerToClient::parse_from_bytes(&packet_payload).unwrap();
+        let message = ZwiftMessage::FromServerTcp(vec![message]);
+        let players = message.get_players().unwrap();
+        assert_eq!(players.len(), 3);
+        let (players, events) = message.get_players_and_events().unwrap();
+        assert_eq!(players.len(), 3);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn corrupt_tcp_frames() {
+        let frames = vec![FROM_SERVER.to_vec(), vec![0xff, 0xff], FROM_SERVER.to_vec()];
+        let (messages, errors) = parse_frames(frames);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind(), "protobuf");
+    }
+
+    #[test]
+    fn player_state_fields() {
+        let packet_payload = FROM_SERVER;
+        let message = ZwiftMessage::FromServer(&packet_payload);
+        let players = message.get_players().unwrap();
+        let runner = &players[1];
+        assert_eq!(runner.sport, Sport::Running);
+        assert!((runner.run_pace().unwrap() - 361.4).abs() < 0.1);
+        assert!((runner.true_distance - 2068.79).abs() < 0.01);
+        assert!((runner.heading_degrees - 331.69).abs() < 0.01);
+        assert!((runner.work_kj - 75.18).abs() < 0.01);
+        assert_eq!(players[0].sport, Sport::Cycling);
+        assert_eq!(players[0].run_pace(), None);
     }
 
     #[test]
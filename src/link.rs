@@ -0,0 +1,179 @@
+use etherparse::SlicedPacket;
+
+use crate::error::{Result, ZwiftCaptureError};
+
+// pcap datalink types, DLT_RAW differs between platforms
+pub const LINKTYPE_NULL: i32 = 0;
+pub const LINKTYPE_ETHERNET: i32 = 1;
+pub const LINKTYPE_RAW: i32 = 101;
+pub const LINKTYPE_RAW_OPENBSD: i32 = 14;
+pub const LINKTYPE_RAW_DLT: i32 = 12;
+pub const LINKTYPE_IEEE802_11: i32 = 105;
+pub const LINKTYPE_LOOP: i32 = 108;
+pub const LINKTYPE_LINUX_SLL: i32 = 113;
+pub const LINKTYPE_IEEE802_11_RADIOTAP: i32 = 127;
+pub const LINKTYPE_IPV4: i32 = 228;
+pub const LINKTYPE_IPV6: i32 = 229;
+pub const LINKTYPE_LINUX_SLL2: i32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+const NULL_HEADER_LEN: usize = 4;
+const SLL_HEADER_LEN: usize = 16;
+const SLL2_HEADER_LEN: usize = 20;
+const VLAN_TAG_LEN: usize = 4;
+const LLC_SNAP_LEN: usize = 8;
+
+#[derive(Debug, PartialEq)]
+enum LinkPayload<'a> {
+    Ethernet(&'a [u8]),
+    Ip(&'a [u8]),
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(ZwiftCaptureError::TruncatedLinkHeader),
+    }
+}
+
+fn skip(data: &[u8], length: usize) -> Result<&[u8]> {
+    data.get(length..)
+        .ok_or(ZwiftCaptureError::TruncatedLinkHeader)
+}
+
+// ip packet after ethertype, skipping vlan tags
+fn ip_payload(ethertype: u16, data: &[u8]) -> Result<LinkPayload> {
+    match ethertype {
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => Ok(LinkPayload::Ip(data)),
+        ETHERTYPE_VLAN | ETHERTYPE_QINQ => {
+            ip_payload(read_u16(data, 2)?, skip(data, VLAN_TAG_LEN)?)
+        }
+        _ => Err(ZwiftCaptureError::UnsupportedEtherType(ethertype)),
+    }
+}
+
+fn ieee802_11_payload(data: &[u8]) -> Result<LinkPayload> {
+    let frame_control = data
+        .get(0..2)
+        .ok_or(ZwiftCaptureError::TruncatedLinkHeader)?;
+    let frame_type = (frame_control[0] >> 2) & 0x3;
+    let subtype = frame_control[0] >> 4;
+    let flags = frame_control[1];
+    // only unprotected data frames carry ip packets
+    if frame_type != 2 || flags & 0x40 != 0 {
+        return Err(ZwiftCaptureError::NotDataFrame);
+    }
+    let mut header_len = 24;
+    if flags & 0x3 == 0x3 {
+        // to ds and from ds, 4th address present
+        header_len += 6;
+    }
+    if subtype & 0x8 != 0 {
+        // qos control
+        header_len += 2;
+    }
+    let llc = skip(data, header_len)?;
+    ip_payload(read_u16(llc, 6)?, skip(llc, LLC_SNAP_LEN)?)
+}
+
+fn strip_link_header(linktype: i32, data: &[u8]) -> Result<LinkPayload> {
+    match linktype {
+        LINKTYPE_ETHERNET => Ok(LinkPayload::Ethernet(data)),
+        LINKTYPE_RAW | LINKTYPE_RAW_DLT | LINKTYPE_RAW_OPENBSD | LINKTYPE_IPV4 | LINKTYPE_IPV6 => {
+            Ok(LinkPayload::Ip(data))
+        }
+        // address family in host or network byte order, ip version is enough
+        LINKTYPE_NULL | LINKTYPE_LOOP => Ok(LinkPayload::Ip(skip(data, NULL_HEADER_LEN)?)),
+        LINKTYPE_LINUX_SLL => ip_payload(read_u16(data, 14)?, skip(data, SLL_HEADER_LEN)?),
+        LINKTYPE_LINUX_SLL2 => ip_payload(read_u16(data, 0)?, skip(data, SLL2_HEADER_LEN)?),
+        LINKTYPE_IEEE802_11 => ieee802_11_payload(data),
+        LINKTYPE_IEEE802_11_RADIOTAP => {
+            // radiotap length is little endian
+            let length = data
+                .get(2..4)
+                .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+                .ok_or(ZwiftCaptureError::TruncatedLinkHeader)?;
+            ieee802_11_payload(skip(data, length)?)
+        }
+        _ => Err(ZwiftCaptureError::UnsupportedLinkType(linktype)),
+    }
+}
+
+pub fn slice_packet(linktype: i32, data: &[u8]) -> Result<SlicedPacket> {
+    let sliced = match strip_link_header(linktype, data)? {
+        LinkPayload::Ethernet(data) => SlicedPacket::from_ethernet(data)?,
+        LinkPayload::Ip(data) => SlicedPacket::from_ip(data)?,
+    };
+    Ok(sliced)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::error::ZwiftCaptureError;
+    use crate::link::*;
+    use hex_literal::hex;
+
+    const IP: [u8; 4] = hex!("45000054");
+
+    #[test]
+    fn linux_cooked_headers() {
+        let sll = [&hex!("0000000100060000000000000000 0800")[..], &IP].concat();
+        assert_eq!(
+            strip_link_header(LINKTYPE_LINUX_SLL, &sll).unwrap(),
+            LinkPayload::Ip(&IP)
+        );
+        let sll2 = [
+            &hex!("0800 0000 00000002 0001 00 06 0000000000000000")[..],
+            &IP,
+        ]
+        .concat();
+        assert_eq!(
+            strip_link_header(LINKTYPE_LINUX_SLL2, &sll2).unwrap(),
+            LinkPayload::Ip(&IP)
+        );
+        let vlan = [
+            &hex!("0000000100060000000000000000 8100 0064 0800")[..],
+            &IP,
+        ]
+        .concat();
+        assert_eq!(
+            strip_link_header(LINKTYPE_LINUX_SLL, &vlan).unwrap(),
+            LinkPayload::Ip(&IP)
+        );
+    }
+
+    #[test]
+    fn loopback_and_wifi_headers() {
+        let null = [&hex!("02000000")[..], &IP].concat();
+        assert_eq!(
+            strip_link_header(LINKTYPE_NULL, &null).unwrap(),
+            LinkPayload::Ip(&IP)
+        );
+        let radiotap = [
+            &hex!("00000800 00000000")[..],
+            &hex!("0801 0000 000000000000 000000000000 000000000000 0000")[..],
+            &hex!("aaaa03000000 0800")[..],
+            &IP,
+        ]
+        .concat();
+        assert_eq!(
+            strip_link_header(LINKTYPE_IEEE802_11_RADIOTAP, &radiotap).unwrap(),
+            LinkPayload::Ip(&IP)
+        );
+        // beacon
+        let beacon = hex!("8000 0000 ffffffffffff 000000000000 000000000000 0000");
+        match strip_link_header(LINKTYPE_IEEE802_11, &beacon) {
+            Err(ZwiftCaptureError::NotDataFrame) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        match strip_link_header(147, &IP) {
+            Err(ZwiftCaptureError::UnsupportedLinkType(147)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
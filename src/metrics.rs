@@ -0,0 +1,338 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::{self, BufReader};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pcap::Stat;
+
+use crate::error::{Result, ZwiftCaptureError};
+use crate::http::{read_request, respond};
+use crate::world::WorldState;
+use crate::{Direction, Event, Player};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// scrapers silent for this long are disconnected
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// messages per second are averaged over this window
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+// message counts per second of wall time
+#[derive(Default)]
+struct RateMeter {
+    buckets: VecDeque<(Instant, u64)>,
+}
+
+impl RateMeter {
+    fn add(&mut self, now: Instant) {
+        match self.buckets.back_mut() {
+            Some((second, count)) if now.duration_since(*second) < Duration::from_secs(1) => {
+                *count += 1
+            }
+            _ => self.buckets.push_back((now, 1)),
+        }
+        self.expire(now);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((second, _)) = self.buckets.front() {
+            if now.duration_since(*second) < RATE_WINDOW {
+                break;
+            }
+            self.buckets.pop_front();
+        }
+    }
+
+    fn rate(&self, now: Instant) -> f64 {
+        let count: u64 = self
+            .buckets
+            .iter()
+            .filter(|(second, _)| now.duration_since(*second) < RATE_WINDOW)
+            .map(|(_, count)| count)
+            .sum();
+        count as f64 / RATE_WINDOW.as_secs_f64()
+    }
+}
+
+// capture health and rider gauges in openmetrics text format
+#[derive(Default)]
+pub struct Metrics {
+    stat: Option<Stat>,
+    errors: BTreeMap<&'static str, u64>,
+    from_server: u64,
+    to_server: u64,
+    from_server_rate: RateMeter,
+    to_server_rate: RateMeter,
+    world: WorldState,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn observe(&mut self, result: &Result<Event>) {
+        match result {
+            Ok(event) => self.observe_event(event),
+            Err(error) => self.observe_error(error),
+        }
+    }
+
+    pub fn observe_event(&mut self, event: &Event) {
+        let now = Instant::now();
+        match event.direction() {
+            Direction::FromServer => {
+                self.from_server += 1;
+                self.from_server_rate.add(now);
+            }
+            Direction::ToServer => {
+                self.to_server += 1;
+                self.to_server_rate.add(now);
+            }
+        }
+        self.world.ingest(event);
+    }
+
+    pub fn observe_error(&mut self, error: &ZwiftCaptureError) {
+        *self.errors.entry(error.kind()).or_insert(0) += 1;
+    }
+
+    // latest ZwiftCapture::stats, counters are cumulative
+    pub fn observe_stat(&mut self, stat: Stat) {
+        self.stat = Some(stat);
+    }
+
+    pub fn render(&self) -> String {
+        let now = Instant::now();
+        let mut out = String::new();
+
+        if let Some(stat) = &self.stat {
+            let counters = [
+                (
+                    "zwift_pcap_packets_received",
+                    "packets received by the filter",
+                    stat.received,
+                ),
+                (
+                    "zwift_pcap_packets_dropped",
+                    "packets dropped by the kernel buffer",
+                    stat.dropped,
+                ),
+                (
+                    "zwift_pcap_packets_if_dropped",
+                    "packets dropped by the interface",
+                    stat.if_dropped,
+                ),
+            ];
+            for (name, help, value) in counters.iter() {
+                writeln!(out, "# TYPE {} counter", name).unwrap();
+                writeln!(out, "# HELP {} {}", name, help).unwrap();
+                writeln!(out, "{}_total {}", name, value).unwrap();
+            }
+        }
+
+        out.push_str("# TYPE zwift_decode_errors counter\n");
+        out.push_str("# HELP zwift_decode_errors packets that failed to decode\n");
+        for (kind, count) in &self.errors {
+            writeln!(
+                out,
+                "zwift_decode_errors_total{{kind=\"{}\"}} {}",
+                kind, count
+            )
+            .unwrap();
+        }
+
+        out.push_str("# TYPE zwift_messages counter\n");
+        out.push_str("# HELP zwift_messages decoded messages\n");
+        writeln!(
+            out,
+            "zwift_messages_total{{direction=\"from_server\"}} {}",
+            self.from_server
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "zwift_messages_total{{direction=\"to_server\"}} {}",
+            self.to_server
+        )
+        .unwrap();
+
+        out.push_str("# TYPE zwift_messages_per_second gauge\n");
+        out.push_str(
+            "# HELP zwift_messages_per_second decoded messages over the last 10 seconds\n",
+        );
+        writeln!(
+            out,
+            "zwift_messages_per_second{{direction=\"from_server\"}} {}",
+            self.from_server_rate.rate(now)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "zwift_messages_per_second{{direction=\"to_server\"}} {}",
+            self.to_server_rate.rate(now)
+        )
+        .unwrap();
+
+        out.push_str("# TYPE zwift_riders_tracked gauge\n");
+        out.push_str("# HELP zwift_riders_tracked riders seen within the eviction timeout\n");
+        writeln!(out, "zwift_riders_tracked {}", self.world.len()).unwrap();
+
+        let mut riders: Vec<_> = self.world.riders().map(|state| &state.player).collect();
+        riders.sort_by_key(|player| player.id);
+        write_gauge(
+            &mut out,
+            "zwift_rider_power_watts",
+            "watts",
+            &riders,
+            |player| player.power,
+        );
+        write_gauge(
+            &mut out,
+            "zwift_rider_heartrate_bpm",
+            "bpm",
+            &riders,
+            |player| player.heartrate,
+        );
+        write_gauge(
+            &mut out,
+            "zwift_rider_cadence_rpm",
+            "rpm",
+            &riders,
+            |player| player.cadence,
+        );
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+// per rider gauge labelled with rider id
+fn write_gauge(
+    out: &mut String,
+    name: &str,
+    unit: &str,
+    riders: &[&Player],
+    value: impl Fn(&Player) -> i32,
+) {
+    writeln!(out, "# TYPE {} gauge", name).unwrap();
+    writeln!(out, "# UNIT {} {}", name, unit).unwrap();
+    for player in riders {
+        writeln!(
+            out,
+            "{}{{rider_id=\"{}\"}} {}",
+            name,
+            player.id,
+            value(player)
+        )
+        .unwrap();
+    }
+}
+
+// serves GET /metrics from a background thread
+pub struct MetricsExporter {
+    address: SocketAddr,
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl MetricsExporter {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let metrics = Arc::new(Mutex::new(Metrics::new()));
+        let shared = metrics.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    // client went away or stalled
+                    let _ = handle(stream, &shared);
+                });
+            }
+        });
+        Ok(MetricsExporter { address, metrics })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    // shared with the capture loop
+    pub fn metrics(&self) -> Arc<Mutex<Metrics>> {
+        self.metrics.clone()
+    }
+}
+
+fn handle(mut stream: TcpStream, metrics: &Mutex<Metrics>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let request = read_request(&mut BufReader::new(stream.try_clone()?))?;
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => {
+            let body = metrics.lock().unwrap().render();
+            respond(&mut stream, "200 OK", CONTENT_TYPE, &body)
+        }
+        ("GET", _) => respond(&mut stream, "404 Not Found", "text/plain", ""),
+        _ => respond(&mut stream, "405 Method Not Allowed", "text/plain", ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use crate::error::ZwiftCaptureError;
+    use crate::metrics::{Metrics, MetricsExporter};
+    use crate::zwift_messages::{PlayerState, ServerToClient};
+    use crate::Event;
+
+    fn event(id: i32, power: i32) -> Event {
+        let mut state = PlayerState::new();
+        state.set_id(id);
+        state.set_worldTime(1_000);
+        state.set_power(power);
+        let mut message = ServerToClient::new();
+        message.mut_player_states().push(state);
+        Event::from_server(message)
+    }
+
+    #[test]
+    fn render_metrics() {
+        let mut metrics = Metrics::new();
+        metrics.observe(&Ok(event(42, 250)));
+        metrics.observe(&Err(ZwiftCaptureError::UnexpectedMessage));
+        let text = metrics.render();
+        assert!(text.contains("zwift_messages_total{direction=\"from_server\"} 1\n"));
+        assert!(text.contains("zwift_messages_per_second{direction=\"from_server\"} 0.1\n"));
+        assert!(text.contains("zwift_decode_errors_total{kind=\"unexpected_message\"} 1\n"));
+        assert!(text.contains("zwift_riders_tracked 1\n"));
+        assert!(text.contains("zwift_rider_power_watts{rider_id=\"42\"} 250\n"));
+        assert!(!text.contains("zwift_pcap"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn serve_metrics() {
+        let exporter = MetricsExporter::bind("127.0.0.1:0").unwrap();
+        exporter
+            .metrics()
+            .lock()
+            .unwrap()
+            .observe_event(&event(1, 100));
+        // a silent client doesn't hold up others
+        let _idle = TcpStream::connect(exporter.local_addr()).unwrap();
+        let mut stream = TcpStream::connect(exporter.local_addr()).unwrap();
+        write!(stream, "GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("application/openmetrics-text"));
+        assert!(response.contains("zwift_rider_power_watts{rider_id=\"1\"} 100"));
+    }
+}
@@ -0,0 +1,271 @@
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pcap::{Activated, Capture};
+
+use crate::builder::ZwiftCaptureBuilder;
+use crate::error::Result;
+use crate::{Event, ZwiftCapture};
+
+// a live interface silent this long stops holding back the others
+pub const IDLE_DELAY: Duration = Duration::from_millis(100);
+
+// events buffered ahead of the consumer per interface, capture threads block when full
+const CAPACITY: usize = 1024;
+// live reads wake up this often to notice cancellation unless set on the builder, millis
+const READ_TIMEOUT: i32 = 250;
+// checks idle live captures this often
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct Source {
+    receiver: Receiver<Result<Event>>,
+    // earliest event not returned yet
+    head: Option<Event>,
+    live: bool,
+    finished: bool,
+    last_seen: Instant,
+}
+
+impl Source {
+    // returns errors, keeps events as head
+    fn receive(&mut self, received: Result<Event>) -> Option<Result<Event>> {
+        self.last_seen = Instant::now();
+        match received {
+            Ok(event) => {
+                self.head = Some(event);
+                None
+            }
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    // no head yet and more may come, files always hold back the merge
+    fn waiting(&self) -> bool {
+        self.head.is_none()
+            && !self.finished
+            && (!self.live || self.last_seen.elapsed() < IDLE_DELAY)
+    }
+}
+
+// reads several captures on their own threads and merges their events by capture time,
+// each event tagged with its interface
+pub struct MultiCapture {
+    sources: Vec<Source>,
+    cancelled: Arc<AtomicBool>,
+    interfaces: Vec<String>,
+}
+
+impl Default for MultiCapture {
+    fn default() -> Self {
+        MultiCapture::new()
+    }
+}
+
+impl MultiCapture {
+    pub fn new() -> Self {
+        MultiCapture {
+            sources: vec![],
+            cancelled: Arc::new(AtomicBool::new(false)),
+            interfaces: vec![],
+        }
+    }
+
+    // opens every device with the same options, in immediate mode as buffered packets
+    // could arrive after IDLE_DELAY and out of order
+    pub fn open(builder: &ZwiftCaptureBuilder, devices: &[&str]) -> Result<Self> {
+        let mut multi = MultiCapture::new();
+        for device in devices {
+            let capture = builder
+                .clone()
+                .device(device)
+                .default_timeout(READ_TIMEOUT)
+                .immediate_mode(true)
+                .open()?;
+            multi.add(device, capture);
+        }
+        Ok(multi)
+    }
+
+    // live captures need a read timeout for close to stop them while idle,
+    // and immediate mode to keep capture time order
+    pub fn add<T>(&mut self, interface: &str, mut capture: ZwiftCapture<Capture<T>>)
+    where
+        T: Activated + Send + 'static,
+    {
+        // only devices have an interface, files are read to the end
+        let live = capture.interface.is_some();
+        capture.interface = Some(interface.to_string());
+        self.interfaces.push(interface.to_string());
+        let (sender, receiver) = sync_channel(CAPACITY);
+        let stop = self.cancelled.clone();
+        thread::spawn(move || {
+            capture.forward(&stop, |event| sender.send(event).is_ok());
+        });
+        self.add_receiver(receiver, live);
+    }
+
+    fn add_receiver(&mut self, receiver: Receiver<Result<Event>>, live: bool) {
+        self.sources.push(Source {
+            receiver,
+            head: None,
+            live,
+            finished: false,
+            last_seen: Instant::now(),
+        });
+    }
+
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    // k-way merge, the earliest head goes out once every other capture has a head or ended,
+    // errors are not delayed, returns None once every capture ended
+    pub fn next_event(&mut self) -> Option<Result<Event>> {
+        loop {
+            for source in self.sources.iter_mut() {
+                if source.head.is_some() || source.finished {
+                    continue;
+                }
+                match source.receiver.try_recv() {
+                    Ok(received) => {
+                        if let Some(error) = source.receive(received) {
+                            return Some(error);
+                        }
+                    }
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Disconnected) => source.finished = true,
+                }
+            }
+            let waiting = self.sources.iter().position(|source| source.waiting());
+            let earliest = self
+                .sources
+                .iter()
+                .enumerate()
+                .filter_map(|(index, source)| Some((source.head.as_ref()?.capture_time, index)))
+                .min();
+            let source = match (waiting, earliest) {
+                (None, Some((_, index))) => {
+                    return self.sources[index].head.take().map(Ok);
+                }
+                (None, None) if self.sources.iter().all(|source| source.finished) => {
+                    return None;
+                }
+                (None, None) => {
+                    // live captures all idle, wait for the next one to speak
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                (Some(index), _) => &mut self.sources[index],
+            };
+            let wait = if source.live {
+                IDLE_DELAY
+                    .checked_sub(source.last_seen.elapsed())
+                    .unwrap_or_default()
+            } else {
+                IDLE_DELAY
+            };
+            match source.receiver.recv_timeout(wait) {
+                Ok(received) => {
+                    if let Some(error) = source.receive(received) {
+                        return Some(error);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => source.finished = true,
+            }
+        }
+    }
+
+    // stops the capture threads, buffered events can still be read
+    pub fn close(&mut self) {
+        self.cancelled.store(true, atomic::Ordering::Relaxed);
+    }
+}
+
+impl Iterator for MultiCapture {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event()
+    }
+}
+
+impl Drop for MultiCapture {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::mpsc::sync_channel;
+    use std::thread;
+
+    use hex_literal::hex;
+
+    use crate::error::Result;
+    use crate::fixtures::TempCapture;
+    use crate::multi::{MultiCapture, IDLE_DELAY};
+    use crate::zwift_messages::ServerToClient;
+    use crate::{Event, ZwiftCapture};
+
+    fn event(capture_time: i64) -> Result<Event> {
+        let mut event = Event::from_server(ServerToClient::new());
+        event.capture_time = capture_time;
+        Ok(event)
+    }
+
+    #[test]
+    fn wait_for_lagging_file() {
+        let mut multi = MultiCapture::new();
+        let (sender, receiver) = sync_channel(4);
+        sender.send(event(1)).unwrap();
+        sender.send(event(4)).unwrap();
+        drop(sender);
+        multi.add_receiver(receiver, false);
+        let (sender, receiver) = sync_channel(4);
+        multi.add_receiver(receiver, false);
+        let lagging = thread::spawn(move || {
+            thread::sleep(IDLE_DELAY * 2);
+            sender.send(event(2)).unwrap();
+            sender.send(event(3)).unwrap();
+        });
+
+        let times: Vec<_> = multi.map(|event| event.unwrap().capture_time).collect();
+        lagging.join().unwrap();
+        assert_eq!(times, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn merge_by_capture_time() {
+        let payload = hex!("08011086d30618d5a3fbcce805");
+        let mut multi = MultiCapture::new();
+        let mut files = vec![];
+        for (interface, seconds) in &[("eth0", [1, 4]), ("wlan0", [2, 3])] {
+            let file = TempCapture::new(&payload, seconds);
+            multi.add(interface, ZwiftCapture::try_from_file(file.path()).unwrap());
+            files.push(file);
+        }
+        assert_eq!(multi.interfaces(), &["eth0", "wlan0"]);
+
+        let events: Vec<_> = multi
+            .map(|event| {
+                let event = event.unwrap();
+                (event.capture_time / 1_000_000, event.interface.unwrap())
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (1, "eth0".to_string()),
+                (2, "wlan0".to_string()),
+                (3, "wlan0".to_string()),
+                (4, "eth0".to_string()),
+            ]
+        );
+    }
+}
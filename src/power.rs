@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+
+use crate::recorder::{RecorderConfig, RideRecorder};
+use crate::{Event, Player};
+
+// seconds, 1s - 60min
+pub const CURVE_DURATIONS: [usize; 14] = [
+    1, 3, 5, 10, 15, 30, 60, 120, 300, 600, 1200, 1800, 2400, 3600,
+];
+
+// rolling window for normalized power, sec
+const NP_WINDOW: usize = 30;
+
+// average of the last window samples, samples are 1 Hz
+pub fn rolling_average(power: &[f64], window: usize) -> Option<f64> {
+    if window == 0 || power.len() < window {
+        return None;
+    }
+    let last = &power[power.len() - window..];
+    Some(last.iter().sum::<f64>() / window as f64)
+}
+
+pub fn average(power: &[f64]) -> Option<f64> {
+    rolling_average(power, power.len())
+}
+
+pub fn normalized_power(power: &[f64]) -> Option<f64> {
+    if power.len() < NP_WINDOW {
+        return None;
+    }
+    let mut sum: f64 = power[..NP_WINDOW].iter().sum();
+    let mut total = (sum / NP_WINDOW as f64).powi(4);
+    for ix in NP_WINDOW..power.len() {
+        sum += power[ix] - power[ix - NP_WINDOW];
+        total += (sum / NP_WINDOW as f64).powi(4);
+    }
+    let count = power.len() - NP_WINDOW + 1;
+    Some((total / count as f64).powf(0.25))
+}
+
+pub fn intensity_factor(normalized_power: f64, ftp: f64) -> f64 {
+    normalized_power / ftp
+}
+
+// training stress score, 100 is one hour at ftp
+pub fn training_stress_score(duration: f64, normalized_power: f64, ftp: f64) -> f64 {
+    let intensity = intensity_factor(normalized_power, ftp);
+    duration * normalized_power * intensity / (ftp * 3600.) * 100.
+}
+
+pub fn work(power: &[f64]) -> f64 {
+    power.iter().sum::<f64>() / 1000.
+}
+
+// best average power for a duration, sec
+pub fn mean_max(power: &[f64], duration: usize) -> Option<f64> {
+    if duration == 0 || power.len() < duration {
+        return None;
+    }
+    let mut sum: f64 = power[..duration].iter().sum();
+    let mut best = sum;
+    for ix in duration..power.len() {
+        sum += power[ix] - power[ix - duration];
+        best = best.max(sum);
+    }
+    Some(best / duration as f64)
+}
+
+pub fn mean_max_curve(power: &[f64]) -> Vec<(usize, f64)> {
+    CURVE_DURATIONS
+        .iter()
+        .filter_map(|&duration| mean_max(power, duration).map(|watts| (duration, watts)))
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PowerSummary {
+    pub rider_id: i32,
+    pub duration: usize, // sec of world time, gaps included
+    pub average: Option<f64>,
+    pub average_3s: Option<f64>,
+    pub average_10s: Option<f64>,
+    pub average_30s: Option<f64>,
+    pub normalized_power: Option<f64>,
+    pub intensity_factor: Option<f64>,
+    pub training_stress_score: Option<f64>,
+    pub work: f64, // kJ
+    pub curve: Vec<(usize, f64)>,
+}
+
+impl PowerSummary {
+    // ftp enables intensity factor and tss
+    pub fn from(rider_id: i32, power: &[f64], ftp: Option<f64>) -> Self {
+        let normalized_power = normalized_power(power);
+        let ftp = ftp.filter(|&ftp| ftp > 0.);
+        let duration = power.len();
+        PowerSummary {
+            rider_id,
+            duration,
+            average: average(power),
+            average_3s: rolling_average(power, 3),
+            average_10s: rolling_average(power, 10),
+            average_30s: rolling_average(power, 30),
+            normalized_power,
+            intensity_factor: ftp
+                .and_then(|ftp| normalized_power.map(|np| intensity_factor(np, ftp))),
+            training_stress_score: ftp.and_then(|ftp| {
+                normalized_power.map(|np| training_stress_score(duration as f64, np, ftp))
+            }),
+            work: work(power),
+            curve: mean_max_curve(power),
+        }
+    }
+}
+
+// power analytics for every rider in view
+pub struct PowerTracker {
+    recorder: RideRecorder,
+}
+
+impl Default for PowerTracker {
+    fn default() -> Self {
+        PowerTracker::new()
+    }
+}
+
+impl PowerTracker {
+    pub fn new() -> Self {
+        PowerTracker {
+            recorder: RideRecorder::new(RecorderConfig {
+                resample: true,
+                ..RecorderConfig::default()
+            }),
+        }
+    }
+
+    pub fn ingest(&mut self, event: &Event) {
+        self.recorder.ingest(event);
+    }
+
+    pub fn add(&mut self, player: &Player) -> bool {
+        self.recorder.add(player)
+    }
+
+    // 1 Hz over the whole world time span, seconds without updates count as zero power
+    pub fn power(&self, rider_id: i32) -> Option<Vec<f64>> {
+        let series = self.recorder.series(rider_id)?;
+        let mut power = vec![];
+        let mut previous: Option<i64> = None;
+        for (&world_time, &watts) in series.world_time.iter().zip(&series.power) {
+            if let Some(previous) = previous {
+                let missing = ((world_time - previous) / 1000 - 1).max(0);
+                power.resize(power.len() + missing as usize, 0.);
+            }
+            power.push(watts as f64);
+            previous = Some(world_time);
+        }
+        Some(power)
+    }
+
+    pub fn summary(&self, rider_id: i32, ftp: Option<f64>) -> Option<PowerSummary> {
+        self.power(rider_id)
+            .map(|power| PowerSummary::from(rider_id, &power, ftp))
+    }
+
+    pub fn summaries(&self, ftp: Option<f64>) -> Vec<PowerSummary> {
+        self.recorder
+            .rider_ids()
+            .filter_map(|&rider_id| self.summary(rider_id, ftp))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::power::*;
+    use crate::zwift_messages::PlayerState;
+    use crate::Player;
+
+    #[test]
+    fn steady_power() {
+        let power = vec![200.; 3600];
+        let summary = PowerSummary::from(1, &power, Some(200.));
+        assert_eq!(summary.normalized_power, Some(200.));
+        assert_eq!(summary.intensity_factor, Some(1.));
+        assert!((summary.training_stress_score.unwrap() - 100.).abs() < 1e-9);
+        assert_eq!(summary.work, 720.);
+        assert_eq!(summary.curve.len(), CURVE_DURATIONS.len());
+    }
+
+    #[test]
+    fn intervals() {
+        let mut power = vec![100.; 60];
+        power.extend(vec![400.; 30]);
+        power.extend(vec![100.; 30]);
+        assert_eq!(mean_max(&power, 30), Some(400.));
+        assert_eq!(mean_max(&power, 60), Some(250.));
+        assert_eq!(rolling_average(&power, 3), Some(100.));
+        assert_eq!(mean_max(&power, 600), None);
+        assert!(normalized_power(&power).unwrap() > average(&power).unwrap());
+    }
+
+    #[test]
+    fn gaps_count_as_zero_power() {
+        let player = |world_time: i64| {
+            let mut state = PlayerState::new();
+            state.set_id(1);
+            state.set_worldTime(world_time);
+            state.set_power(200);
+            Player::from(&state)
+        };
+        let mut tracker = PowerTracker::new();
+        // no updates between 10 and 30 sec
+        for second in (0..=10).chain(30..=39) {
+            tracker.add(&player(second * 1000));
+        }
+        let summary = tracker.summary(1, None).unwrap();
+        assert_eq!(summary.duration, 40);
+        assert_eq!(summary.average, Some(105.));
+        assert_eq!(summary.work, 4.2);
+        assert_eq!(summary.curve[3], (10, 200.));
+        assert!(summary.curve[5].1 < 200.);
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::zwift_messages::{ClientToServer, ServerToClient};
+use crate::{DecodedMessage, Direction, Event};
+
+// sequence numbers this far behind the highest are no longer tracked as missing,
+// further back the sender restarted counting
+const REORDER_WINDOW: i64 = 1024;
+// multi-part updates waiting for their remaining parts
+const PENDING_UPDATES: usize = 16;
+// recent client world times matched against echoes
+const SENT_HISTORY: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SequenceStats {
+    pub received: u64,
+    pub lost: u64, // missing now, reordered packets are not counted
+    pub duplicates: u64,
+    pub reordered: u64,
+}
+
+impl SequenceStats {
+    fn merge(&mut self, other: &SequenceStats) {
+        self.received += other.received;
+        self.lost += other.lost;
+        self.duplicates += other.duplicates;
+        self.reordered += other.reordered;
+    }
+}
+
+#[derive(Debug, Default)]
+struct SequenceTracker {
+    stats: SequenceStats,
+    highest: Option<i64>,
+    missing: BTreeSet<i64>,
+}
+
+impl SequenceTracker {
+    fn push(&mut self, seqno: i64) {
+        self.stats.received += 1;
+        let highest = match self.highest {
+            Some(highest) => highest,
+            None => {
+                self.highest = Some(seqno);
+                return;
+            }
+        };
+        if seqno < highest - REORDER_WINDOW {
+            // reconnected, the sender starts counting again
+            self.highest = Some(seqno);
+            self.missing.clear();
+        } else if seqno > highest {
+            let gap = seqno - highest - 1;
+            self.stats.lost += gap as u64;
+            // huge gaps are reconnects, do not remember every number
+            self.missing
+                .extend((highest + 1).max(seqno - REORDER_WINDOW)..seqno);
+            self.highest = Some(seqno);
+            let oldest = seqno - REORDER_WINDOW;
+            self.missing = self.missing.split_off(&oldest);
+        } else if self.missing.remove(&seqno) {
+            self.stats.lost -= 1;
+            self.stats.reordered += 1;
+        } else {
+            self.stats.duplicates += 1;
+        }
+    }
+}
+
+// joins ServerToClient updates split into num_msgs parts
+#[derive(Default)]
+pub struct UpdateAssembler {
+    // keyed by world time shared by all parts
+    pending: BTreeMap<i64, Vec<ServerToClient>>,
+    complete: u64,
+    incomplete: u64,
+}
+
+impl UpdateAssembler {
+    pub fn new() -> Self {
+        UpdateAssembler::default()
+    }
+
+    // returns the whole update once the last part arrived
+    pub fn push(&mut self, message: &ServerToClient) -> Option<ServerToClient> {
+        let num_msgs = message.get_num_msgs();
+        if num_msgs <= 1 {
+            return Some(message.clone());
+        }
+        let world_time = message.get_world_time();
+        let parts = self.pending.entry(world_time).or_default();
+        if parts
+            .iter()
+            .all(|part| part.get_msgnum() != message.get_msgnum())
+        {
+            parts.push(message.clone());
+        }
+        if parts.len() < num_msgs as usize {
+            while self.pending.len() > PENDING_UPDATES {
+                let oldest = *self.pending.keys().next().unwrap();
+                self.pending.remove(&oldest);
+                self.incomplete += 1;
+            }
+            return None;
+        }
+
+        let mut parts = self.pending.remove(&world_time).unwrap();
+        parts.sort_by_key(|part| part.get_msgnum());
+        let mut update = parts[0].clone();
+        for part in &parts[1..] {
+            update
+                .mut_player_states()
+                .extend(part.get_player_states().iter().cloned());
+            update
+                .mut_player_updates()
+                .extend(part.get_player_updates().iter().cloned());
+        }
+        update.set_msgnum(0);
+        update.set_num_msgs(1);
+        self.complete += 1;
+        Some(update)
+    }
+
+    // multi-part updates with all parts joined
+    pub fn complete(&self) -> u64 {
+        self.complete
+    }
+
+    // dropped before all parts arrived
+    pub fn incomplete(&self) -> u64 {
+        self.incomplete
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LatencyStats {
+    pub samples: u64,
+    pub min: i64, // millis
+    pub max: i64,
+    pub mean: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QualitySummary {
+    pub from_server: SequenceStats,
+    pub to_server: SequenceStats,
+    pub multipart_complete: u64,
+    pub multipart_incomplete: u64,
+    // round trip of own state echoed by the server, between packet capture times
+    pub latency: Option<LatencyStats>,
+}
+
+// loss, reordering and latency of a capture
+#[derive(Default)]
+pub struct QualityTracker {
+    sequences: HashMap<(i32, Direction), SequenceTracker>,
+    assembler: UpdateAssembler,
+    // world and capture times of recent client updates per rider
+    sent: HashMap<i32, VecDeque<(i64, i64)>>,
+    latency: Option<LatencyStats>,
+}
+
+impl QualityTracker {
+    pub fn new() -> Self {
+        QualityTracker::default()
+    }
+
+    // returns reassembled server update if the event completed one
+    pub fn ingest(&mut self, event: &Event) -> Option<ServerToClient> {
+        match &event.message {
+            DecodedMessage::FromServer(message) => {
+                self.ingest_from_server(message, event.capture_time)
+            }
+            DecodedMessage::ToServer(_, message) => {
+                self.ingest_to_server(message, event.capture_time);
+                None
+            }
+        }
+    }
+
+    fn sequence(&mut self, rider_id: i32, direction: Direction) -> &mut SequenceTracker {
+        self.sequences.entry((rider_id, direction)).or_default()
+    }
+
+    fn ingest_to_server(&mut self, message: &ClientToServer, capture_time: i64) {
+        let rider_id = message.get_rider_id();
+        self.sequence(rider_id, Direction::ToServer)
+            .push(message.get_seqno() as i64);
+        // no round trip without packet timestamps
+        if capture_time == 0 {
+            return;
+        }
+        let sent = self.sent.entry(rider_id).or_default();
+        sent.push_back((message.get_world_time(), capture_time));
+        while sent.len() > SENT_HISTORY {
+            sent.pop_front();
+        }
+    }
+
+    fn ingest_from_server(
+        &mut self,
+        message: &ServerToClient,
+        capture_time: i64,
+    ) -> Option<ServerToClient> {
+        let rider_id = message.get_rider_id();
+        // parts of one update share the sequence number
+        if message.get_msgnum() <= 1 {
+            self.sequence(rider_id, Direction::FromServer)
+                .push(message.get_seqno() as i64);
+        }
+
+        let latencies: Vec<i64> = match self.sent.get(&rider_id) {
+            Some(sent) if capture_time != 0 => message
+                .get_player_states()
+                .iter()
+                .filter(|state| state.get_id() == rider_id)
+                .filter_map(|state| {
+                    sent.iter()
+                        .find(|(world_time, _)| *world_time == state.get_worldTime())
+                })
+                .map(|(_, sent_time)| (capture_time - sent_time) / 1000)
+                .collect(),
+            _ => vec![],
+        };
+        for latency in latencies {
+            self.add_latency(latency);
+        }
+
+        self.assembler.push(message)
+    }
+
+    fn add_latency(&mut self, latency: i64) {
+        if latency < 0 {
+            return;
+        }
+        self.latency = Some(match self.latency {
+            None => LatencyStats {
+                samples: 1,
+                min: latency,
+                max: latency,
+                mean: latency as f64,
+            },
+            Some(stats) => {
+                let samples = stats.samples + 1;
+                LatencyStats {
+                    samples,
+                    min: stats.min.min(latency),
+                    max: stats.max.max(latency),
+                    mean: stats.mean + (latency as f64 - stats.mean) / samples as f64,
+                }
+            }
+        });
+    }
+
+    pub fn summary(&self) -> QualitySummary {
+        let mut from_server = SequenceStats::default();
+        let mut to_server = SequenceStats::default();
+        for ((_, direction), tracker) in &self.sequences {
+            match direction {
+                Direction::FromServer => from_server.merge(&tracker.stats),
+                Direction::ToServer => to_server.merge(&tracker.stats),
+            }
+        }
+        QualitySummary {
+            from_server,
+            to_server,
+            multipart_complete: self.assembler.complete(),
+            multipart_incomplete: self.assembler.incomplete(),
+            latency: self.latency,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::datagram::ClientDatagramHeader;
+    use crate::quality::{QualityTracker, SequenceTracker, UpdateAssembler};
+    use crate::zwift_messages::{ClientToServer, PlayerState, ServerToClient};
+    use crate::Event;
+
+    #[test]
+    fn sequence_gaps() {
+        let mut tracker = SequenceTracker::default();
+        for seqno in &[1, 2, 5, 3, 3, 6, 6] {
+            tracker.push(*seqno);
+        }
+        assert_eq!(tracker.stats.received, 7);
+        assert_eq!(tracker.stats.lost, 1);
+        assert_eq!(tracker.stats.reordered, 1);
+        assert_eq!(tracker.stats.duplicates, 2);
+
+        // counting restarts after a reconnect
+        let mut tracker = SequenceTracker::default();
+        for seqno in &[5_000, 5_001, 1, 2, 4] {
+            tracker.push(*seqno);
+        }
+        assert_eq!(tracker.stats.received, 5);
+        assert_eq!(tracker.stats.duplicates, 0);
+        assert_eq!(tracker.stats.lost, 1);
+    }
+
+    #[test]
+    fn reassemble_parts() {
+        let part = |msgnum: i32, id: i32| {
+            let mut state = PlayerState::new();
+            state.set_id(id);
+            let mut message = ServerToClient::new();
+            message.set_world_time(1_000);
+            message.set_seqno(7);
+            message.set_num_msgs(2);
+            message.set_msgnum(msgnum);
+            message.mut_player_states().push(state);
+            message
+        };
+        let mut assembler = UpdateAssembler::new();
+        assert!(assembler.push(&part(2, 20)).is_none());
+        let update = assembler.push(&part(1, 10)).unwrap();
+        let ids: Vec<_> = update
+            .get_player_states()
+            .iter()
+            .map(|state| state.get_id())
+            .collect();
+        assert_eq!(ids, vec![10, 20]);
+
+        let mut tracker = QualityTracker::new();
+        tracker.ingest(&Event::from_server(part(1, 10)));
+        tracker.ingest(&Event::from_server(part(2, 20)));
+        let mut single = part(1, 10);
+        single.set_num_msgs(1);
+        single.set_seqno(8);
+        tracker.ingest(&Event::from_server(single));
+        let summary = tracker.summary();
+        assert_eq!(summary.multipart_complete, 1);
+        assert_eq!(summary.from_server.received, 2);
+    }
+
+    #[test]
+    fn latency_from_capture_times() {
+        let mut tracker = QualityTracker::new();
+        for (world_time, capture_time) in &[(1_000, 5_000_000), (1_100, 5_100_000)] {
+            let mut message = ClientToServer::new();
+            message.set_rider_id(42);
+            message.set_world_time(*world_time);
+            let header = ClientDatagramHeader {
+                header_length: 0,
+                connection_id: 0,
+                sequence: 0,
+                trailer: [0; 4],
+            };
+            let mut event = Event::to_server(header, message);
+            event.capture_time = *capture_time;
+            tracker.ingest(&event);
+        }
+        // echo of the first update, 150 ms after it was sent
+        let mut state = PlayerState::new();
+        state.set_id(42);
+        state.set_worldTime(1_000);
+        let mut message = ServerToClient::new();
+        message.set_rider_id(42);
+        message.mut_player_states().push(state);
+        let mut event = Event::from_server(message);
+        event.capture_time = 5_150_000;
+        tracker.ingest(&event);
+
+        let latency = tracker.summary().latency.unwrap();
+        assert_eq!((latency.samples, latency.min, latency.max), (1, 150, 150));
+    }
+}
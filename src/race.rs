@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::zwift_messages::EventPositions;
+use crate::{DecodedMessage, Event};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub position: i32, // 1 is leading
+    pub rider_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Standings {
+    pub world_time: i64,     // millis
+    pub event_subgroup: i32, // group id of the observed rider, 0 if not seen yet
+    pub num_riders: i32,
+    pub position: i32, // of the observed rider
+    pub riders: Vec<Standing>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PositionChange {
+    pub rider_id: i32,
+    pub from: i32,
+    pub to: i32,
+    pub world_time: i64,
+}
+
+// race standings of the event the observed rider is in, from ServerToClient.event_positions
+//   the rider list is ordered, it is anchored on the observed rider when listed,
+//   otherwise taken as the leaders, partly guessed
+#[derive(Default)]
+pub struct EventTracker {
+    rider_id: i32, // observed rider, receiver of the server messages
+    event_subgroup: i32,
+    standings: Option<Standings>,
+    positions: HashMap<i32, i32>,
+    history: Vec<(i64, i32)>, // world time, position of the observed rider on change
+}
+
+impl EventTracker {
+    pub fn new() -> Self {
+        EventTracker::default()
+    }
+
+    // returns riders whose position changed
+    pub fn ingest(&mut self, event: &Event) -> Vec<PositionChange> {
+        if let DecodedMessage::FromServer(message) = &event.message {
+            let rider_id = message.get_rider_id();
+            if rider_id != 0 && rider_id != self.rider_id {
+                self.rider_id = rider_id;
+                self.event_subgroup = 0;
+                self.reset();
+            }
+        }
+        let observed = event
+            .players
+            .iter()
+            .find(|player| self.rider_id != 0 && player.id == self.rider_id);
+        if let Some(player) = observed {
+            if player.group_id != self.event_subgroup {
+                // joined another event, or left one
+                self.event_subgroup = player.group_id;
+                self.reset();
+            }
+        }
+        match &event.message {
+            DecodedMessage::FromServer(message) if message.has_event_positions() => {
+                self.update(message.get_world_time(), message.get_event_positions())
+            }
+            _ => vec![],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.standings = None;
+        self.positions.clear();
+        self.history.clear();
+    }
+
+    fn update(&mut self, world_time: i64, event_positions: &EventPositions) -> Vec<PositionChange> {
+        let position = event_positions.get_position();
+        let ids: Vec<i32> = event_positions
+            .get_eventRiderPosition()
+            .iter()
+            .map(|rider| rider.get_rider_id())
+            .collect();
+        let first = match ids.iter().position(|id| *id == self.rider_id) {
+            Some(index) => position - index as i32,
+            None => 1,
+        };
+        let mut riders: Vec<Standing> = ids
+            .iter()
+            .enumerate()
+            .map(|(index, rider_id)| Standing {
+                position: first + index as i32,
+                rider_id: *rider_id,
+            })
+            .collect();
+        if position > 0 && !ids.contains(&self.rider_id) {
+            riders.push(Standing {
+                position,
+                rider_id: self.rider_id,
+            });
+            riders.sort_by_key(|standing| standing.position);
+        }
+
+        let mut changes = vec![];
+        for standing in &riders {
+            match self.positions.insert(standing.rider_id, standing.position) {
+                Some(from) if from != standing.position => changes.push(PositionChange {
+                    rider_id: standing.rider_id,
+                    from,
+                    to: standing.position,
+                    world_time,
+                }),
+                _ => {}
+            }
+        }
+        if position > 0 && self.history.last().map(|(_, last)| *last) != Some(position) {
+            self.history.push((world_time, position));
+        }
+        self.standings = Some(Standings {
+            world_time,
+            event_subgroup: self.event_subgroup,
+            num_riders: event_positions.get_num_riders(),
+            position,
+            riders,
+        });
+        changes
+    }
+
+    pub fn standings(&self) -> Option<&Standings> {
+        self.standings.as_ref()
+    }
+
+    // positions of the observed rider since the event started, on change
+    pub fn history(&self) -> &[(i64, i32)] {
+        &self.history
+    }
+
+    pub fn rider_id(&self) -> i32 {
+        self.rider_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::race::{EventTracker, PositionChange};
+    use crate::zwift_messages::{
+        EventPositions, EventPositions_EventRiderPosition, PlayerState, ServerToClient,
+    };
+    use crate::Event;
+
+    fn event(world_time: i64, group_id: i32, position: i32, ids: &[i32]) -> Event {
+        let mut positions = EventPositions::new();
+        positions.set_position(position);
+        positions.set_num_riders(40);
+        for id in ids {
+            let mut rider = EventPositions_EventRiderPosition::new();
+            rider.set_rider_id(*id);
+            positions.mut_eventRiderPosition().push(rider);
+        }
+        let mut state = PlayerState::new();
+        state.set_id(7);
+        state.set_worldTime(world_time);
+        state.set_groupId(group_id);
+        let mut message = ServerToClient::new();
+        message.set_rider_id(7);
+        message.set_world_time(world_time);
+        message.set_event_positions(positions);
+        message.mut_player_states().push(state);
+        Event::from_server(message)
+    }
+
+    #[test]
+    fn standings_and_changes() {
+        let mut tracker = EventTracker::new();
+        assert!(tracker.ingest(&event(1_000, 5, 12, &[3, 7, 9])).is_empty());
+        let standings = tracker.standings().unwrap();
+        let positions: Vec<_> = standings
+            .riders
+            .iter()
+            .map(|standing| (standing.position, standing.rider_id))
+            .collect();
+        assert_eq!(positions, vec![(11, 3), (12, 7), (13, 9)]);
+        assert_eq!(standings.num_riders, 40);
+        assert_eq!(standings.event_subgroup, 5);
+
+        let changes = tracker.ingest(&event(2_000, 5, 11, &[7, 3, 9]));
+        assert_eq!(
+            changes,
+            vec![
+                PositionChange {
+                    rider_id: 7,
+                    from: 12,
+                    to: 11,
+                    world_time: 2_000
+                },
+                PositionChange {
+                    rider_id: 3,
+                    from: 11,
+                    to: 12,
+                    world_time: 2_000
+                },
+            ]
+        );
+        assert_eq!(tracker.history(), &[(1_000, 12), (2_000, 11)]);
+    }
+
+    #[test]
+    fn new_event_resets() {
+        let mut tracker = EventTracker::new();
+        tracker.ingest(&event(1_000, 5, 6, &[1, 2, 4]));
+        // observed rider not listed, list is the leaders
+        let standings = tracker.standings().unwrap();
+        assert_eq!(standings.riders.len(), 4);
+        assert_eq!(standings.riders[3].rider_id, 7);
+
+        assert!(tracker.ingest(&event(2_000, 6, 1, &[7])).is_empty());
+        assert_eq!(tracker.history(), &[(2_000, 1)]);
+        assert_eq!(tracker.standings().unwrap().event_subgroup, 6);
+    }
+}
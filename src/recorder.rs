@@ -0,0 +1,327 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Event, Player};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub world_time: i64, // millis
+    pub power: i32,      // watts
+    pub heartrate: i32,  // bpm
+    pub cadence: i32,    // rpm
+    pub speed: f64,      // m per sec
+    pub distance: f64,   // m
+    pub altitude: f64,   // m
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Sample {
+    pub fn from(player: &Player) -> Self {
+        Sample {
+            world_time: player.world_time,
+            power: player.power,
+            heartrate: player.heartrate,
+            cadence: player.cadence,
+            speed: player.speed,
+            distance: player.distance as f64,
+            altitude: player.altitude,
+            x: player.x,
+            y: player.y,
+        }
+    }
+
+    fn interpolate(&self, next: &Sample, world_time: i64) -> Sample {
+        let ratio =
+            (world_time - self.world_time) as f64 / (next.world_time - self.world_time) as f64;
+        let lerp = |a: f64, b: f64| a + (b - a) * ratio;
+        let lerp_i32 = |a: i32, b: i32| lerp(a as f64, b as f64).round() as i32;
+        Sample {
+            world_time,
+            power: lerp_i32(self.power, next.power),
+            heartrate: lerp_i32(self.heartrate, next.heartrate),
+            cadence: lerp_i32(self.cadence, next.cadence),
+            speed: lerp(self.speed, next.speed),
+            distance: lerp(self.distance, next.distance),
+            altitude: lerp(self.altitude, next.altitude),
+            x: lerp(self.x, next.x),
+            y: lerp(self.y, next.y),
+        }
+    }
+
+    fn same_values(&self, other: &Sample) -> bool {
+        Sample {
+            world_time: other.world_time,
+            ..self.clone()
+        } == *other
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecorderConfig {
+    // samples kept per rider, oldest dropped first
+    pub capacity: usize,
+    // one sample per second of world time
+    pub resample: bool,
+    // longer gaps without any update are not filled when resampling and show
+    // as a jump in world_time, millis
+    pub max_gap: i64,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        RecorderConfig {
+            capacity: 4 * 60 * 60,
+            resample: true,
+            max_gap: 10_000,
+        }
+    }
+}
+
+// columnar ring buffer of rider samples
+#[derive(Debug, Clone, Default)]
+pub struct RiderSeries {
+    pub world_time: VecDeque<i64>,
+    pub power: VecDeque<i32>,
+    pub heartrate: VecDeque<i32>,
+    pub cadence: VecDeque<i32>,
+    pub speed: VecDeque<f64>,
+    pub distance: VecDeque<f64>,
+    pub altitude: VecDeque<f64>,
+    pub x: VecDeque<f64>,
+    pub y: VecDeque<f64>,
+}
+
+impl RiderSeries {
+    pub fn len(&self) -> usize {
+        self.world_time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.world_time.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Sample> {
+        Some(Sample {
+            world_time: *self.world_time.get(index)?,
+            power: self.power[index],
+            heartrate: self.heartrate[index],
+            cadence: self.cadence[index],
+            speed: self.speed[index],
+            distance: self.distance[index],
+            altitude: self.altitude[index],
+            x: self.x[index],
+            y: self.y[index],
+        })
+    }
+
+    pub fn last(&self) -> Option<Sample> {
+        self.len().checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Sample> + '_ {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+
+    fn push(&mut self, sample: Sample, capacity: usize) {
+        while self.len() >= capacity.max(1) {
+            self.pop_front();
+        }
+        self.world_time.push_back(sample.world_time);
+        self.power.push_back(sample.power);
+        self.heartrate.push_back(sample.heartrate);
+        self.cadence.push_back(sample.cadence);
+        self.speed.push_back(sample.speed);
+        self.distance.push_back(sample.distance);
+        self.altitude.push_back(sample.altitude);
+        self.x.push_back(sample.x);
+        self.y.push_back(sample.y);
+    }
+
+    fn pop_front(&mut self) {
+        self.world_time.pop_front();
+        self.power.pop_front();
+        self.heartrate.pop_front();
+        self.cadence.pop_front();
+        self.speed.pop_front();
+        self.distance.pop_front();
+        self.altitude.pop_front();
+        self.x.pop_front();
+        self.y.pop_front();
+    }
+
+    fn pop_back(&mut self) {
+        self.world_time.pop_back();
+        self.power.pop_back();
+        self.heartrate.pop_back();
+        self.cadence.pop_back();
+        self.speed.pop_back();
+        self.distance.pop_back();
+        self.altitude.pop_back();
+        self.x.pop_back();
+        self.y.pop_back();
+    }
+}
+
+#[derive(Debug, Default)]
+struct RiderRecording {
+    series: RiderSeries,
+    // latest raw sample, resampled series lags behind it
+    last: Option<Sample>,
+}
+
+pub struct RideRecorder {
+    config: RecorderConfig,
+    riders: HashMap<i32, RiderRecording>,
+}
+
+impl Default for RideRecorder {
+    fn default() -> Self {
+        RideRecorder::new(RecorderConfig::default())
+    }
+}
+
+impl RideRecorder {
+    pub fn new(config: RecorderConfig) -> Self {
+        RideRecorder {
+            config,
+            riders: HashMap::new(),
+        }
+    }
+
+    pub fn ingest(&mut self, event: &Event) {
+        for player in &event.players {
+            self.add(player);
+        }
+    }
+
+    // returns false for out of order samples and repeats within a second,
+    // a stopped rider still gets a sample every second
+    pub fn add(&mut self, player: &Player) -> bool {
+        if player.id == 0 {
+            return false;
+        }
+        let config = self.config;
+        let recording = self.riders.entry(player.id).or_default();
+        let sample = Sample::from(player);
+        if let Some(last) = &recording.last {
+            let same_second =
+                sample.world_time.div_euclid(1000) == last.world_time.div_euclid(1000);
+            if sample.world_time <= last.world_time || (same_second && sample.same_values(last)) {
+                return false;
+            }
+        }
+
+        if !config.resample {
+            recording.series.push(sample.clone(), config.capacity);
+            recording.last = Some(sample);
+            return true;
+        }
+
+        let second = sample.world_time.div_euclid(1000) * 1000;
+        let series = &mut recording.series;
+        match (recording.last.as_ref(), series.world_time.back().copied()) {
+            // fill whole seconds between previous and current sample
+            (Some(last), Some(last_second))
+                if second > last_second
+                    && sample.world_time - last.world_time <= config.max_gap =>
+            {
+                let mut world_time = last_second + 1000;
+                while world_time < second {
+                    series.push(last.interpolate(&sample, world_time), config.capacity);
+                    world_time += 1000;
+                }
+            }
+            (_, Some(last_second)) if second == last_second => {
+                // latest sample within a second wins
+                series.pop_back();
+            }
+            _ => {}
+        }
+        series.push(
+            Sample {
+                world_time: second,
+                ..sample.clone()
+            },
+            config.capacity,
+        );
+        recording.last = Some(sample);
+        true
+    }
+
+    pub fn series(&self, id: i32) -> Option<&RiderSeries> {
+        self.riders.get(&id).map(|recording| &recording.series)
+    }
+
+    pub fn rider_ids(&self) -> impl Iterator<Item = &i32> {
+        self.riders.keys()
+    }
+
+    pub fn remove(&mut self, id: i32) -> Option<RiderSeries> {
+        self.riders.remove(&id).map(|recording| recording.series)
+    }
+
+    // drops riders for which keep returns false, e.g. those no longer tracked
+    pub fn retain<F: FnMut(i32) -> bool>(&mut self, mut keep: F) {
+        self.riders.retain(|id, _| keep(*id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::recorder::{RecorderConfig, RideRecorder};
+    use crate::zwift_messages::PlayerState;
+    use crate::Player;
+
+    fn player(world_time: i64, power: i32) -> Player {
+        let mut state = PlayerState::new();
+        state.set_id(1);
+        state.set_worldTime(world_time);
+        state.set_power(power);
+        Player::from(&state)
+    }
+
+    #[test]
+    fn deduplicate_raw_samples() {
+        let mut recorder = RideRecorder::new(RecorderConfig {
+            capacity: 2,
+            resample: false,
+            max_gap: 0,
+        });
+        assert!(recorder.add(&player(1_000, 100)));
+        assert!(!recorder.add(&player(1_000, 100)));
+        assert!(!recorder.add(&player(900, 150)));
+        assert!(!recorder.add(&player(1_200, 100)));
+        assert!(recorder.add(&player(1_300, 200)));
+        assert!(recorder.add(&player(1_400, 300)));
+        let series = recorder.series(1).unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series.power, vec![200, 300]);
+    }
+
+    #[test]
+    fn resample_to_one_hz() {
+        let mut recorder = RideRecorder::default();
+        recorder.add(&player(10_200, 100));
+        recorder.add(&player(10_700, 150));
+        recorder.add(&player(13_700, 300));
+        let series = recorder.series(1).unwrap();
+        assert_eq!(series.world_time, vec![10_000, 11_000, 12_000, 13_000]);
+        assert_eq!(series.power, vec![150, 165, 215, 300]);
+
+        // stopped for longer than max gap, unchanged updates keep the series at 1 hz
+        for world_time in (14_000..=30_000).step_by(500) {
+            recorder.add(&player(world_time, 0));
+        }
+        let series = recorder.series(1).unwrap();
+        assert_eq!(series.len(), 21);
+        assert_eq!(series.world_time.back(), Some(&30_000));
+        assert!(series
+            .world_time
+            .iter()
+            .zip(series.world_time.iter().skip(1))
+            .all(|(a, b)| b - a == 1000));
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::GameEvent;
+use crate::zwift_messages::RiderAttributes;
+use crate::{Event, Player};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RiderInfo {
+    pub first_name: String,
+    pub last_name: String,
+    pub country_code: Option<i32>,
+    pub avatar: Option<String>,
+}
+
+impl RiderInfo {
+    pub fn display_name(&self) -> String {
+        format!("{} {}", self.first_name, self.last_name)
+            .trim()
+            .to_string()
+    }
+}
+
+// player with the name learned so far
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NamedPlayer {
+    #[serde(flatten)]
+    pub player: Player,
+    pub name: Option<String>,
+    pub country_code: Option<i32>,
+}
+
+// rider id to name, country and avatar, from chat, ride ons, riders entering the world
+// and rider attributes, optionally kept in a json file between sessions
+#[derive(Default)]
+pub struct RiderDirectory {
+    riders: HashMap<i32, RiderInfo>,
+    path: Option<PathBuf>,
+}
+
+impl RiderDirectory {
+    pub fn new() -> Self {
+        RiderDirectory::default()
+    }
+
+    // starts empty if the file does not exist yet
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let riders = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => return Err(error),
+        };
+        Ok(RiderDirectory {
+            riders,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    // writes back to the loaded file, no op for directories not loaded from file
+    pub fn save(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => self.save_to(path),
+            None => Ok(()),
+        }
+    }
+
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        // replace atomically so a crash does not lose the roster
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(&self.riders)?)?;
+        fs::rename(&temporary, path)
+    }
+
+    // empty values do not overwrite known ones
+    pub fn learn(
+        &mut self,
+        rider_id: i32,
+        first_name: &str,
+        last_name: &str,
+        country_code: Option<i32>,
+        avatar: Option<&str>,
+    ) {
+        if rider_id == 0 || (first_name.is_empty() && last_name.is_empty()) {
+            return;
+        }
+        let info = self.riders.entry(rider_id).or_default();
+        if !first_name.is_empty() {
+            info.first_name = first_name.to_string();
+        }
+        if !last_name.is_empty() {
+            info.last_name = last_name.to_string();
+        }
+        if let Some(country_code) = country_code.filter(|&code| code != 0) {
+            info.country_code = Some(country_code);
+        }
+        if let Some(avatar) = avatar.filter(|avatar| !avatar.is_empty()) {
+            info.avatar = Some(avatar.to_string());
+        }
+    }
+
+    // RiderAttributes have no known update kind yet, callers that decoded one pass it here
+    pub fn learn_attributes(&mut self, attributes: &RiderAttributes) {
+        let message = attributes.get_attributeMessage();
+        self.learn(
+            message.get_theirId(),
+            message.get_firstName(),
+            message.get_lastName(),
+            Some(message.get_countryCode()),
+            None,
+        );
+    }
+
+    pub fn ingest(&mut self, event: &Event) {
+        for game_event in &event.game_events {
+            match game_event {
+                GameEvent::Chat {
+                    rider_id,
+                    first_name,
+                    last_name,
+                    avatar,
+                    country_code,
+                    ..
+                } => self.learn(
+                    *rider_id,
+                    first_name,
+                    last_name,
+                    Some(*country_code),
+                    Some(avatar),
+                ),
+                GameEvent::RideOn {
+                    rider_id,
+                    first_name,
+                    last_name,
+                    country_code,
+                    ..
+                } => self.learn(*rider_id, first_name, last_name, Some(*country_code), None),
+                GameEvent::RiderEnteredWorld {
+                    rider_id,
+                    first_name,
+                    last_name,
+                } => self.learn(*rider_id, first_name, last_name, None, None),
+                // unknown payloads are not guessed at, random bytes often decode
+                _ => {}
+            }
+        }
+    }
+
+    pub fn get(&self, rider_id: i32) -> Option<&RiderInfo> {
+        self.riders.get(&rider_id)
+    }
+
+    pub fn display_name(&self, rider_id: i32) -> Option<String> {
+        self.get(rider_id).map(|info| info.display_name())
+    }
+
+    pub fn enrich(&self, player: &Player) -> NamedPlayer {
+        let info = self.get(player.id);
+        NamedPlayer {
+            player: player.clone(),
+            name: info.map(|info| info.display_name()),
+            country_code: info.and_then(|info| info.country_code),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.riders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.riders.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use protobuf::Message;
+
+    use crate::events::GameEvent;
+    use crate::fixtures::TempFile;
+    use crate::roster::RiderDirectory;
+    use crate::zwift_messages::{PlayerState, RiderAttributes, ServerToClient};
+    use crate::Event;
+
+    fn event(game_events: Vec<GameEvent>) -> Event {
+        let mut state = PlayerState::new();
+        state.set_id(7);
+        let mut message = ServerToClient::new();
+        message.mut_player_states().push(state);
+        let mut event = Event::from_server(message);
+        event.game_events = game_events;
+        event
+    }
+
+    #[test]
+    fn learn_names() {
+        let mut directory = RiderDirectory::new();
+        // payloads of unknown kind are never taken as names
+        let mut attributes = RiderAttributes::new();
+        attributes.mut_attributeMessage().set_theirId(9);
+        attributes
+            .mut_attributeMessage()
+            .set_firstName("Not".to_string());
+        directory.ingest(&event(vec![GameEvent::Unknown {
+            kind: 110,
+            bytes: attributes.write_to_bytes().unwrap(),
+        }]));
+        assert_eq!(directory.display_name(9), None);
+
+        let event = event(vec![
+            GameEvent::RiderEnteredWorld {
+                rider_id: 7,
+                first_name: "Maks".to_string(),
+                last_name: "Sch".to_string(),
+            },
+            GameEvent::RideOn {
+                rider_id: 7,
+                to_rider_id: 8,
+                first_name: "Maks".to_string(),
+                last_name: "Sch".to_string(),
+                country_code: 643,
+            },
+        ]);
+        directory.ingest(&event);
+        assert_eq!(directory.display_name(7).unwrap(), "Maks Sch");
+        let named = directory.enrich(&event.players[0]);
+        assert_eq!(named.name.unwrap(), "Maks Sch");
+        assert_eq!(named.country_code, Some(643));
+        assert_eq!(directory.display_name(8), None);
+    }
+
+    #[test]
+    fn persist_roster() {
+        let file = TempFile::new("json");
+        let mut directory = RiderDirectory::load(file.path()).unwrap();
+        directory.learn(42, "Ada", "L", Some(826), Some("https://avatar"));
+        directory.save().unwrap();
+
+        let directory = RiderDirectory::load(file.path()).unwrap();
+        let info = directory.get(42).unwrap();
+        assert_eq!(info.display_name(), "Ada L");
+        assert_eq!(info.avatar.as_deref(), Some("https://avatar"));
+    }
+}
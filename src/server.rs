@@ -0,0 +1,288 @@
+use std::io::{self, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{sync_channel, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use pcap::{Activated, Capture};
+use serde::Serialize;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::error::{Result, ZwiftCaptureError};
+use crate::events::GameEvent;
+use crate::http::{read_request, respond};
+use crate::recorder::{RideRecorder, Sample};
+use crate::roster::RiderDirectory;
+use crate::world::{RiderState, WorldState};
+use crate::{Event, Player, ZwiftCapture};
+
+const JSON: &str = "application/json";
+
+// updates queued per websocket client, slower clients are disconnected
+const CLIENT_BACKLOG: usize = 1024;
+// idle or stuck connections are dropped after this
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+// websocket reads wake up this often to send queued updates
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// websocket message, one per player update or game event
+#[derive(Serialize)]
+pub enum Update<'a> {
+    Player(&'a Player),
+    GameEvent(&'a GameEvent),
+}
+
+// rider state with the name learned so far
+#[derive(Serialize)]
+struct NamedRider<'a> {
+    #[serde(flatten)]
+    rider: &'a RiderState,
+    name: Option<String>,
+    country_code: Option<i32>,
+}
+
+impl<'a> NamedRider<'a> {
+    fn from(rider: &'a RiderState, roster: &RiderDirectory) -> Self {
+        let info = roster.get(rider.player.id);
+        NamedRider {
+            rider,
+            name: info.map(|info| info.display_name()),
+            country_code: info.and_then(|info| info.country_code),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    world: Mutex<WorldState>,
+    roster: Arc<Mutex<RiderDirectory>>,
+    recorder: Mutex<RideRecorder>,
+    clients: Mutex<Vec<SyncSender<String>>>,
+}
+
+// serves tracked riders over http and pushes updates to websocket clients
+//   GET /riders, /riders/{id}, /riders/{id}/history
+//   GET /ws
+pub struct LiveServer {
+    address: SocketAddr,
+    shared: Arc<Shared>,
+    listener: thread::JoinHandle<()>,
+}
+
+impl LiveServer {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let shared = Arc::new(Shared::default());
+        let state = shared.clone();
+        let listener = thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = state.clone();
+                thread::spawn(move || {
+                    // client went away
+                    let _ = handle(stream, &state);
+                });
+            }
+        });
+        Ok(LiveServer {
+            address,
+            shared,
+            listener,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    // names already known, e.g. loaded from a previous session
+    pub fn set_roster(&self, roster: RiderDirectory) {
+        *self.shared.roster.lock().unwrap() = roster;
+    }
+
+    // shared with the capture loop, e.g. to save it
+    pub fn roster(&self) -> Arc<Mutex<RiderDirectory>> {
+        self.shared.roster.clone()
+    }
+
+    pub fn publish(&self, event: &Event) {
+        {
+            let mut world = self.shared.world.lock().unwrap();
+            world.ingest(event);
+            // history goes with the rider once evicted
+            let mut recorder = self.shared.recorder.lock().unwrap();
+            recorder.ingest(event);
+            recorder.retain(|id| world.get(id).is_some());
+        }
+        self.shared.roster.lock().unwrap().ingest(event);
+
+        let updates = event
+            .players
+            .iter()
+            .map(Update::Player)
+            .chain(event.game_events.iter().map(Update::GameEvent));
+        let lines: Vec<String> = updates
+            .filter_map(|update| serde_json::to_string(&update).ok())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+        self.shared.clients.lock().unwrap().retain(|client| {
+            lines
+                .iter()
+                .all(|line| client.try_send(line.clone()).is_ok())
+        });
+    }
+
+    // publishes every event of the capture, returns at the end of capture file
+    pub fn run<T: Activated>(&self, capture: &mut ZwiftCapture<Capture<T>>) -> Result<()> {
+        while let Some(event) = capture.next_event() {
+            match event {
+                Ok(event) => self.publish(&event),
+                Err(ZwiftCaptureError::Pcap(error)) => return Err(error.into()),
+                // skip corrupt packets
+                Err(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    // blocks serving requests
+    pub fn wait(self) {
+        let _ = self.listener.join();
+    }
+}
+
+fn handle(mut stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    // requests have no body and websocket clients wait for the handshake response
+    let request = read_request(&mut BufReader::new(stream.try_clone()?))?;
+    if request.method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", JSON, "null");
+    }
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let body = match segments.as_slice() {
+        ["ws"] => match &request.websocket_key {
+            Some(key) => return websocket(stream, key, shared),
+            None => return respond(&mut stream, "400 Bad Request", JSON, "null"),
+        },
+        ["riders"] => {
+            let world = shared.world.lock().unwrap();
+            let roster = shared.roster.lock().unwrap();
+            let riders: Vec<_> = world
+                .riders()
+                .map(|rider| NamedRider::from(rider, &roster))
+                .collect();
+            serde_json::to_string(&riders).ok()
+        }
+        ["riders", id] => id.parse().ok().and_then(|id| {
+            let world = shared.world.lock().unwrap();
+            let roster = shared.roster.lock().unwrap();
+            world
+                .get(id)
+                .and_then(|rider| serde_json::to_string(&NamedRider::from(rider, &roster)).ok())
+        }),
+        ["riders", id, "history"] => id.parse().ok().and_then(|id| {
+            let recorder = shared.recorder.lock().unwrap();
+            recorder.series(id).and_then(|series| {
+                let samples: Vec<Sample> = series.iter().collect();
+                serde_json::to_string(&samples).ok()
+            })
+        }),
+        _ => None,
+    };
+    match body {
+        Some(body) => respond(&mut stream, "200 OK", JSON, &body),
+        None => respond(&mut stream, "404 Not Found", JSON, "null"),
+    }
+}
+
+fn websocket(mut stream: TcpStream, key: &str, shared: &Shared) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    )?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let (sender, receiver) = sync_channel(CLIENT_BACKLOG);
+    shared.clients.lock().unwrap().push(sender);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    loop {
+        // pings and close frames are answered by the next read
+        match socket.read() {
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(error))
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut => {}
+            // closed by the client or broken
+            Err(_) => return Ok(()),
+        }
+        loop {
+            match receiver.try_recv() {
+                Ok(line) => {
+                    if socket.send(Message::Text(line)).is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use crate::roster::RiderDirectory;
+    use crate::server::LiveServer;
+    use crate::zwift_messages::{PlayerState, ServerToClient};
+    use crate::Event;
+
+    fn get(server: &LiveServer, path: &str) -> String {
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serve_riders() {
+        let server = LiveServer::bind("127.0.0.1:0").unwrap();
+        let mut roster = RiderDirectory::new();
+        roster.learn(42, "Ada", "L", None, None);
+        server.set_roster(roster);
+        let mut state = PlayerState::new();
+        state.set_id(42);
+        state.set_worldTime(1_000);
+        state.set_power(250);
+        let mut message = ServerToClient::new();
+        message.mut_player_states().push(state);
+        server.publish(&Event::from_server(message));
+
+        let response = get(&server, "/riders");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("\"power\":250"));
+        assert!(response.contains("\"name\":\"Ada L\""));
+        assert!(get(&server, "/riders/42/history").starts_with("HTTP/1.1 200 OK"));
+        assert!(get(&server, "/riders/7").starts_with("HTTP/1.1 404"));
+        assert!(get(&server, "/nothing").starts_with("HTTP/1.1 404"));
+
+        // riders evicted from the world lose their history
+        let mut state = PlayerState::new();
+        state.set_id(43);
+        state.set_worldTime(100_000);
+        let mut message = ServerToClient::new();
+        message.mut_player_states().push(state);
+        server.publish(&Event::from_server(message));
+        assert!(get(&server, "/riders/42/history").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::zwift_messages::{ServerAddress, ServerToClient};
+use crate::{DecodedMessage, Direction, Event};
+
+// ServerAddress, fields other than ip not known yet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerEndpoint {
+    pub ip: IpAddr,
+    pub f1: i32,
+    pub f2: i32,
+    pub f4: i32,
+    pub f5: u32,
+    pub f6: u32,
+}
+
+impl ServerEndpoint {
+    pub fn from(address: &ServerAddress) -> Option<Self> {
+        Some(ServerEndpoint {
+            ip: address.get_ip().parse().ok()?,
+            f1: address.get_f1(),
+            f2: address.get_f2(),
+            f4: address.get_f4(),
+            f5: address.get_f5(),
+            f6: address.get_f6(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerPoolInfo {
+    pub f1: i32,
+    pub f2: i32,
+    pub f4: i32,
+    pub addresses: Vec<ServerEndpoint>,
+}
+
+// session metadata announced by the server
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SessionInfo {
+    pub rider_id: i32, // local rider, 0 until seen
+    pub local_ip: Option<IpAddr>,
+    pub relays: Vec<ServerEndpoint>, // servers1, latest announcement
+    pub pools: Vec<ServerPoolInfo>,  // servers2, latest announcement
+    pub tag11: i64,
+    pub tag17: i64,
+    // announced in servers1 and servers2, never guessed from traffic as a misclassified
+    // datagram would then flip the direction of every later one
+    pub servers: BTreeSet<IpAddr>,
+}
+
+impl SessionInfo {
+    pub fn new() -> Self {
+        SessionInfo::default()
+    }
+
+    pub fn ingest(&mut self, event: &Event) {
+        if let DecodedMessage::FromServer(message) = &event.message {
+            self.learn(message);
+        }
+    }
+
+    pub fn learn(&mut self, message: &ServerToClient) {
+        if message.get_rider_id() != 0 {
+            self.rider_id = message.get_rider_id();
+        }
+        if let Ok(ip) = message.get_local_ip().parse() {
+            self.local_ip = Some(ip);
+        }
+        if message.get_tag11() != 0 {
+            self.tag11 = message.get_tag11();
+        }
+        if message.get_tag17() != 0 {
+            self.tag17 = message.get_tag17();
+        }
+        if message.has_servers1() {
+            self.relays = message
+                .get_servers1()
+                .get_addresses()
+                .iter()
+                .filter_map(ServerEndpoint::from)
+                .collect();
+            self.servers
+                .extend(self.relays.iter().map(|endpoint| endpoint.ip));
+        }
+        if message.has_servers2() {
+            self.pools = message
+                .get_servers2()
+                .get_pool()
+                .iter()
+                .map(|pool| ServerPoolInfo {
+                    f1: pool.get_f1(),
+                    f2: pool.get_f2(),
+                    f4: pool.get_f4(),
+                    addresses: pool
+                        .get_addresses()
+                        .iter()
+                        .filter_map(ServerEndpoint::from)
+                        .collect(),
+                })
+                .collect();
+            let ips: Vec<IpAddr> = self
+                .pools
+                .iter()
+                .flat_map(|pool| pool.addresses.iter().map(|endpoint| endpoint.ip))
+                .collect();
+            self.servers.extend(ips);
+        }
+    }
+
+    pub fn is_server(&self, ip: &IpAddr) -> bool {
+        self.servers.contains(ip)
+    }
+
+    // direction of a datagram between known endpoints, None if neither is known
+    pub fn direction(&self, source: IpAddr, destination: IpAddr) -> Option<Direction> {
+        if self.is_server(&source) || Some(destination) == self.local_ip {
+            Some(Direction::FromServer)
+        } else if self.is_server(&destination) || Some(source) == self.local_ip {
+            Some(Direction::ToServer)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::net::IpAddr;
+
+    use crate::session::SessionInfo;
+    use crate::zwift_messages::{ServerAddress, ServerPool, ServerToClient};
+    use crate::{Direction, Event};
+
+    fn address(ip: &str) -> ServerAddress {
+        let mut address = ServerAddress::new();
+        address.set_ip(ip.to_string());
+        address.set_f1(1);
+        address
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn learn_session() {
+        let mut message = ServerToClient::new();
+        message.set_rider_id(108934);
+        message.set_local_ip("192.168.1.20".to_string());
+        message.set_tag11(77);
+        message
+            .mut_servers1()
+            .mut_addresses()
+            .push(address("52.1.2.3"));
+        message
+            .mut_servers1()
+            .mut_addresses()
+            .push(address("not an ip"));
+        let mut pool = ServerPool::new();
+        pool.mut_addresses().push(address("52.4.5.6"));
+        pool.mut_addresses().push(address("52.4.5.7"));
+        message.mut_servers2().mut_pool().push(pool);
+
+        let mut session = SessionInfo::new();
+        session.ingest(&Event::from_server(message));
+        assert_eq!(session.rider_id, 108934);
+        assert_eq!(session.local_ip, Some(ip("192.168.1.20")));
+        assert_eq!(session.tag11, 77);
+        assert_eq!(session.relays.len(), 1);
+        assert_eq!(session.pools[0].addresses.len(), 2);
+        assert!(session.is_server(&ip("52.4.5.7")));
+        assert_eq!(session.servers.len(), 3);
+
+        // later messages without metadata keep it
+        session.ingest(&Event::from_server(ServerToClient::new()));
+        assert_eq!(session.rider_id, 108934);
+        assert_eq!(session.relays.len(), 1);
+    }
+
+    #[test]
+    fn classify_endpoints() {
+        let mut session = SessionInfo::new();
+        session.servers.insert(ip("52.1.2.3"));
+        session.local_ip = Some(ip("10.0.0.2"));
+        let direction = |source, destination| session.direction(ip(source), ip(destination));
+        assert_eq!(
+            direction("52.1.2.3", "192.168.1.20"),
+            Some(Direction::FromServer)
+        );
+        assert_eq!(
+            direction("192.168.1.20", "52.1.2.3"),
+            Some(Direction::ToServer)
+        );
+        assert_eq!(direction("10.0.0.2", "8.8.8.8"), Some(Direction::ToServer));
+        assert_eq!(direction("8.8.8.8", "1.1.1.1"), None);
+    }
+}
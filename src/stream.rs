@@ -0,0 +1,115 @@
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+
+use futures_core::Stream;
+use pcap::{Activated, Capture, Device};
+use tokio::sync::mpsc;
+
+use crate::error::Result;
+use crate::{Event, ZwiftCapture, CAPTURE_FILTER};
+
+// events buffered ahead of the consumer, capture thread blocks when full
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+// live reads wake up this often to notice cancellation, millis
+const READ_TIMEOUT: i32 = 250;
+
+// drives a blocking capture on its own thread, dropping the stream stops it,
+// live captures need a read timeout for that
+pub struct EventStream {
+    receiver: mpsc::Receiver<Result<Event>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl EventStream {
+    pub fn new<T>(mut capture: ZwiftCapture<Capture<T>>, capacity: usize) -> Self
+    where
+        T: Activated + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let stop = cancelled.clone();
+        thread::spawn(move || {
+            capture.forward(&stop, |event| sender.blocking_send(event).is_ok());
+        });
+        EventStream {
+            receiver,
+            cancelled,
+        }
+    }
+
+    pub fn from_device(device: Device) -> Result<Self> {
+        let mut capture = Capture::from_device(device)?.timeout(READ_TIMEOUT).open()?;
+        capture.filter(CAPTURE_FILTER, true)?;
+        Ok(EventStream::new(
+            ZwiftCapture::with_capture(capture),
+            DEFAULT_CAPACITY,
+        ))
+    }
+
+    pub fn lookup() -> Result<Self> {
+        EventStream::from_device(Device::lookup()?)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Ok(EventStream::new(
+            ZwiftCapture::try_from_file(path)?,
+            DEFAULT_CAPACITY,
+        ))
+    }
+
+    // stops the capture thread, buffered events can still be read
+    pub fn close(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.receiver.close();
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Result<Event>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use futures_core::Stream;
+    use std::future::poll_fn;
+    use std::pin::Pin;
+
+    use crate::fixtures::{TempCapture, FROM_SERVER};
+    use crate::stream::EventStream;
+
+    #[test]
+    fn stream_from_file() {
+        let payload = FROM_SERVER;
+        let file = TempCapture::new(&payload, &[1_600_000_000]);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let events = runtime.block_on(async {
+            let mut stream = EventStream::from_file(file.path()).unwrap();
+            let mut events = vec![];
+            while let Some(event) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                events.push(event.unwrap());
+            }
+            events
+        });
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].players.len(), 3);
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+
+// zwift tcp channel frames are prefixed with 2 bytes big endian length
+const FRAME_HEADER_LEN: usize = 2;
+// out of order segments buffered behind a gap, then the missing data is taken as lost
+// in capture, retransmissions arrive well before
+const MAX_PENDING_SEGMENTS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamKey {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TcpSegment<'a> {
+    pub sequence: u32,
+    pub syn: bool,
+    pub fin: bool,
+    pub rst: bool,
+    pub payload: &'a [u8],
+}
+
+#[derive(Default)]
+struct TcpStream {
+    // sequence number of the next expected byte, None until first data seen
+    next_sequence: Option<u32>,
+    // out of order segments by sequence number
+    pending: BTreeMap<u32, Vec<u8>>,
+    // contiguous bytes not yet split into frames
+    buffer: Vec<u8>,
+    // buffer starts at a frame boundary, false when joined mid stream or after a gap
+    synced: bool,
+}
+
+// data is one or more complete frames, likely starts at a frame boundary
+fn whole_frames(data: &[u8]) -> bool {
+    let mut position = 0;
+    while position + FRAME_HEADER_LEN <= data.len() {
+        let length = u16::from_be_bytes([data[position], data[position + 1]]) as usize;
+        position += FRAME_HEADER_LEN + length;
+    }
+    !data.is_empty() && position == data.len()
+}
+
+impl TcpStream {
+    fn push(&mut self, segment: &TcpSegment) {
+        let mut sequence = segment.sequence;
+        if segment.syn {
+            // syn consumes one sequence number
+            sequence = sequence.wrapping_add(1);
+            self.next_sequence = Some(sequence);
+            self.pending.clear();
+            self.buffer.clear();
+            self.synced = true;
+        }
+        if segment.payload.is_empty() {
+            return;
+        }
+        // joined mid stream, frames start at the first segment of whole frames
+        let next_sequence = *self.next_sequence.get_or_insert(sequence);
+
+        let offset = sequence.wrapping_sub(next_sequence) as i32;
+        if offset > 0 {
+            self.pending
+                .entry(sequence)
+                .or_insert_with(|| segment.payload.to_vec());
+            if self.pending.len() >= MAX_PENDING_SEGMENTS {
+                self.skip();
+            }
+            return;
+        }
+        self.append(sequence, segment.payload);
+        self.drain_pending();
+    }
+
+    // gives up on the missing data, continues from the lowest pending segment
+    fn skip(&mut self) {
+        let next_sequence = match self.next_sequence {
+            Some(next_sequence) => next_sequence,
+            None => return,
+        };
+        let lowest = self
+            .pending
+            .keys()
+            .copied()
+            .min_by_key(|&sequence| sequence.wrapping_sub(next_sequence));
+        if let Some(lowest) = lowest {
+            self.next_sequence = Some(lowest);
+            // a frame may be cut in the gap
+            self.buffer.clear();
+            self.synced = false;
+            self.drain_pending();
+        }
+    }
+
+    // appends segment data at sequence <= next_sequence, skipping already received bytes
+    fn append(&mut self, sequence: u32, data: &[u8]) {
+        let next_sequence = self.next_sequence.unwrap_or(sequence);
+        let overlap = next_sequence.wrapping_sub(sequence) as usize;
+        if overlap >= data.len() {
+            // retransmission of already received data
+            return;
+        }
+        // out of sync data is dropped up to a segment of whole frames
+        if !self.synced && overlap == 0 && whole_frames(data) {
+            self.synced = true;
+        }
+        if self.synced {
+            self.buffer.extend_from_slice(&data[overlap..]);
+        }
+        self.next_sequence = Some(sequence.wrapping_add(data.len() as u32));
+    }
+
+    fn drain_pending(&mut self) {
+        while let Some(next_sequence) = self.next_sequence {
+            let ready = self
+                .pending
+                .keys()
+                .copied()
+                .find(|&sequence| next_sequence.wrapping_sub(sequence) as i32 >= 0);
+            match ready {
+                Some(sequence) => {
+                    let data = self.pending.remove(&sequence).unwrap();
+                    self.append(sequence, &data);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn frames(&mut self) -> Vec<Vec<u8>> {
+        let mut frames = vec![];
+        let mut position = 0;
+        while self.buffer.len() - position >= FRAME_HEADER_LEN {
+            let length =
+                u16::from_be_bytes([self.buffer[position], self.buffer[position + 1]]) as usize;
+            let end = position + FRAME_HEADER_LEN + length;
+            if end > self.buffer.len() {
+                break;
+            }
+            if length > 0 {
+                frames.push(self.buffer[position + FRAME_HEADER_LEN..end].to_vec());
+            }
+            position = end;
+        }
+        self.buffer.drain(..position);
+        frames
+    }
+}
+
+// follows tcp connections and splits them into length prefixed zwift frames
+#[derive(Default)]
+pub struct TcpReassembler {
+    streams: HashMap<StreamKey, TcpStream>,
+}
+
+impl TcpReassembler {
+    pub fn new() -> Self {
+        TcpReassembler::default()
+    }
+
+    // returns complete frames available after the segment
+    pub fn push(&mut self, key: StreamKey, segment: &TcpSegment) -> Vec<Vec<u8>> {
+        if segment.rst {
+            self.streams.remove(&key);
+            return vec![];
+        }
+        let stream = self.streams.entry(key).or_default();
+        stream.push(segment);
+        let frames = stream.frames();
+        if segment.fin {
+            self.streams.remove(&key);
+        }
+        frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{StreamKey, TcpReassembler, TcpSegment, MAX_PENDING_SEGMENTS};
+
+    fn key() -> StreamKey {
+        StreamKey {
+            source: "10.0.0.1:3023".parse().unwrap(),
+            destination: "192.168.1.2:50000".parse().unwrap(),
+        }
+    }
+
+    fn segment(sequence: u32, payload: &[u8]) -> TcpSegment {
+        TcpSegment {
+            sequence,
+            syn: false,
+            fin: false,
+            rst: false,
+            payload,
+        }
+    }
+
+    #[test]
+    fn frames_split_across_segments() {
+        let mut reassembler = TcpReassembler::new();
+        let mut syn = segment(99, &[]);
+        syn.syn = true;
+        assert!(reassembler.push(key(), &syn).is_empty());
+        let frames = reassembler.push(key(), &segment(100, &[0, 3, 1, 2]));
+        assert!(frames.is_empty());
+        let frames = reassembler.push(key(), &segment(104, &[3, 0, 1, 9]));
+        assert_eq!(frames, vec![vec![1, 2, 3], vec![9]]);
+    }
+
+    #[test]
+    fn out_of_order_and_retransmitted_segments() {
+        let mut reassembler = TcpReassembler::new();
+        let mut syn = segment(u32::MAX - 1, &[]);
+        syn.syn = true;
+        assert!(reassembler.push(key(), &syn).is_empty());
+        // sequence wraps around, second half arrives first
+        assert!(reassembler.push(key(), &segment(1, &[2, 3, 4])).is_empty());
+        let frames = reassembler.push(key(), &segment(u32::MAX, &[0, 3]));
+        assert_eq!(frames, vec![vec![2, 3, 4]]);
+        // retransmissions of already received data are dropped
+        assert!(reassembler
+            .push(key(), &segment(u32::MAX, &[0, 3, 2]))
+            .is_empty());
+        assert!(reassembler.push(key(), &segment(1, &[2, 3, 4])).is_empty());
+        let frames = reassembler.push(key(), &segment(3, &[4, 0, 1, 7]));
+        assert_eq!(frames, vec![vec![7]]);
+    }
+
+    #[test]
+    fn resync_after_gap() {
+        let mut reassembler = TcpReassembler::new();
+        // joined mid frame, waits for a segment of whole frames
+        assert!(reassembler.push(key(), &segment(100, &[7, 8])).is_empty());
+        let frames = reassembler.push(key(), &segment(102, &[0, 1, 5]));
+        assert_eq!(frames, vec![vec![5]]);
+
+        // 105..108 lost in capture, the frame cut by it is dropped
+        assert!(reassembler.push(key(), &segment(108, &[9, 9])).is_empty());
+        let mut frames = vec![];
+        for index in 0..MAX_PENDING_SEGMENTS as u8 - 1 {
+            let sequence = 110 + 3 * index as u32;
+            frames = reassembler.push(key(), &segment(sequence, &[0, 1, index]));
+        }
+        assert_eq!(frames.len(), MAX_PENDING_SEGMENTS - 1);
+        assert_eq!(frames[0], vec![0]);
+        let sequence = 110 + 3 * (MAX_PENDING_SEGMENTS as u32 - 1);
+        let frames = reassembler.push(key(), &segment(sequence, &[0, 1, 42]));
+        assert_eq!(frames, vec![vec![42]]);
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::GameEvent;
+use crate::{Event, Player};
+
+// riders not updated for this long are evicted, millis of world time
+pub const DEFAULT_EVICTION_TIMEOUT: i64 = 60_000;
+// world times further ahead need a second source before the clock follows, millis
+const MAX_CLOCK_JUMP: i64 = 300_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RiderState {
+    pub player: Player,
+    pub ride_ons_received: u32,
+}
+
+// freshest known state per rider, keyed by Player::id
+pub struct WorldState {
+    riders: HashMap<i32, RiderState>,
+    eviction_timeout: i64, // millis
+    world_time: i64,       // latest world time seen, millis
+    // source id and time of an unconfirmed jump ahead, 0 for time sync
+    ahead: Option<(i32, i64)>,
+}
+
+impl Default for WorldState {
+    fn default() -> Self {
+        WorldState::new(DEFAULT_EVICTION_TIMEOUT)
+    }
+}
+
+impl WorldState {
+    pub fn new(eviction_timeout: i64) -> Self {
+        WorldState {
+            riders: HashMap::new(),
+            eviction_timeout,
+            world_time: 0,
+            ahead: None,
+        }
+    }
+
+    pub fn ingest(&mut self, event: &Event) {
+        for player in &event.players {
+            self.update_player(player);
+        }
+        for game_event in &event.game_events {
+            self.update_game_event(game_event);
+        }
+        self.evict();
+    }
+
+    // returns false if player is older than known state
+    pub fn update_player(&mut self, player: &Player) -> bool {
+        if player.id == 0 {
+            return false;
+        }
+        self.advance_clock(player.id, player.world_time);
+        match self.riders.get_mut(&player.id) {
+            Some(state) if state.player.world_time > player.world_time => false,
+            Some(state) => {
+                state.player = player.clone();
+                true
+            }
+            None => {
+                self.riders.insert(
+                    player.id,
+                    RiderState {
+                        player: player.clone(),
+                        ride_ons_received: 0,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    pub fn update_game_event(&mut self, game_event: &GameEvent) {
+        match game_event {
+            GameEvent::RideOn { to_rider_id, .. } => {
+                if let Some(state) = self.riders.get_mut(to_rider_id) {
+                    state.ride_ons_received += 1;
+                }
+            }
+            GameEvent::TimeSync { world_time, .. } => {
+                self.advance_clock(0, *world_time);
+            }
+            _ => {}
+        }
+    }
+
+    // one bogus timestamp far in the future would evict every rider
+    fn advance_clock(&mut self, source: i32, world_time: i64) {
+        if world_time <= self.world_time {
+            return;
+        }
+        if self.world_time == 0 || world_time - self.world_time <= MAX_CLOCK_JUMP {
+            self.world_time = world_time;
+            return;
+        }
+        match self.ahead {
+            Some((other, other_time)) if other != source => {
+                self.world_time = world_time.min(other_time);
+                self.ahead = None;
+            }
+            _ => self.ahead = Some((source, world_time)),
+        }
+    }
+
+    // removes riders not seen within eviction timeout
+    pub fn evict(&mut self) {
+        let oldest = self.world_time - self.eviction_timeout;
+        self.riders
+            .retain(|_, state| state.player.world_time >= oldest);
+    }
+
+    pub fn world_time(&self) -> i64 {
+        self.world_time
+    }
+
+    pub fn get(&self, id: i32) -> Option<&RiderState> {
+        self.riders.get(&id)
+    }
+
+    pub fn riders(&self) -> impl Iterator<Item = &RiderState> {
+        self.riders.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.riders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.riders.is_empty()
+    }
+
+    // riders within distance in metres of given rider, excluding the rider
+    pub fn riders_within(&self, id: i32, distance: f64) -> Vec<&RiderState> {
+        let center = match self.riders.get(&id) {
+            Some(state) => &state.player,
+            None => return vec![],
+        };
+        self.riders
+            .values()
+            .filter(|state| state.player.id != id)
+            .filter(|state| {
+                let dx = state.player.x - center.x;
+                let dy = state.player.y - center.y;
+                (dx * dx + dy * dy).sqrt() <= distance
+            })
+            .collect()
+    }
+
+    pub fn riders_in_group(&self, group_id: i32) -> Vec<&RiderState> {
+        self.riders
+            .values()
+            .filter(|state| state.player.group_id == group_id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::world::WorldState;
+    use crate::zwift_messages::PlayerState;
+    use crate::Player;
+
+    fn player(id: i32, world_time: i64, x: f32, group_id: i32) -> Player {
+        let mut state = PlayerState::new();
+        state.set_id(id);
+        state.set_worldTime(world_time);
+        state.set_x(x * 100.);
+        state.set_groupId(group_id);
+        Player::from(&state)
+    }
+
+    #[test]
+    fn keeps_freshest_state() {
+        let mut world = WorldState::new(10_000);
+        assert!(world.update_player(&player(1, 2_000, 10., 0)));
+        assert!(!world.update_player(&player(1, 1_000, 20., 0)));
+        assert_eq!(world.get(1).unwrap().player.x, 10.);
+
+        world.update_player(&player(2, 15_000, 0., 0));
+        world.evict();
+        assert!(world.get(1).is_none());
+        assert_eq!(world.len(), 1);
+    }
+
+    #[test]
+    fn nearby_and_group_queries() {
+        let mut world = WorldState::default();
+        world.update_player(&player(1, 1_000, 0., 7));
+        world.update_player(&player(2, 1_000, 15., 7));
+        world.update_player(&player(3, 1_000, 200., 0));
+        let nearby = world.riders_within(1, 20.);
+        assert_eq!(nearby.len(), 1);
+        assert_eq!(nearby[0].player.id, 2);
+        assert_eq!(world.riders_in_group(7).len(), 2);
+    }
+
+    #[test]
+    fn clock_ignores_single_outlier() {
+        let mut world = WorldState::new(10_000);
+        world.update_player(&player(1, 1_000, 0., 0));
+        world.update_player(&player(2, 1_500, 0., 0));
+        world.update_player(&player(3, 10_000_000, 0., 0));
+        world.update_player(&player(3, 10_000_100, 0., 0));
+        world.evict();
+        assert_eq!(world.world_time(), 1_500);
+        assert_eq!(world.len(), 3);
+
+        // a second rider confirms the jump
+        world.update_player(&player(4, 10_000_200, 0., 0));
+        world.evict();
+        assert_eq!(world.world_time(), 10_000_100);
+        assert_eq!(world.len(), 2);
+    }
+}